@@ -1,4 +1,3 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -44,10 +43,38 @@ pub struct ArticlesResponse {
     pub articles_count: u32,
 }
 
+/// One download link listed for an article, e.g. a MEGA or direct mirror of its files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArticleDownloadLink {
+    pub id: i64,
+    #[serde(rename = "articleId")]
+    pub article_id: i64,
+    pub name: String,
+    pub url: String,
+    #[serde(rename = "isActive")]
+    pub is_active: bool,
+    pub status: String,
+}
+
+impl ArticleDownloadLink {
+    /// Only active, moderator-approved links are offered for download.
+    pub fn is_available(&self) -> bool {
+        self.is_active && self.status == "APPROVED"
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ArticleDownloadsResponse {
+    Wrapped { links: Vec<ArticleDownloadLink> },
+    Bare(Vec<ArticleDownloadLink>),
+}
+
 
 
 #[tauri::command]
 pub async fn get_articles(
+    app: tauri::AppHandle,
     token: Option<String>,
     limit: Option<u32>,
     offset: Option<u32>,
@@ -57,7 +84,7 @@ pub async fn get_articles(
     tags: Option<String>,
     sort: Option<String>,
 ) -> Result<Vec<Article>, String> {
-    let client = Client::new();
+    let client = crate::proxy::client(&app)?;
     let mut url = url::Url::parse("https://api.chanomhub.online/api/articles").map_err(|e| e.to_string())?;
 
     if let Some(limit_val) = limit { url.query_pairs_mut().append_pair("limit", &limit_val.to_string()); }
@@ -90,3 +117,29 @@ pub async fn get_articles(
     println!("Successfully parsed {} articles.", articles_response.articles.len());
     Ok(articles_response.articles)
 }
+
+pub async fn fetch_article_downloads(
+    app: &tauri::AppHandle,
+    article_id: i64,
+    token: Option<&str>,
+) -> Result<Vec<ArticleDownloadLink>, String> {
+    let client = crate::proxy::client(app)?;
+    let url = format!("https://api.chanomhub.online/api/downloads/article/{}", article_id);
+
+    let mut request = client.get(&url).header("accept", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_else(|_| "<failed to read response text>".to_string());
+        return Err(format!("Failed to fetch downloads: {} - {}", status, error_text));
+    }
+
+    let links = match response.json::<ArticleDownloadsResponse>().await.map_err(|e| e.to_string())? {
+        ArticleDownloadsResponse::Wrapped { links } | ArticleDownloadsResponse::Bare(links) => links,
+    };
+    Ok(links)
+}
@@ -1,10 +1,15 @@
-use sevenz_rust::decompress_file;
+use crate::throttle::PROGRESS_INTERVAL;
+use sevenz_rust::{Password, SevenZReader};
+use std::cell::Cell;
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{self};
-use std::path::Path;
-use std::process::Command;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+use unrar::error::{Code, UnrarError, When};
 use zip::read::ZipArchive;
+use zip::result::ZipError;
 
 #[derive(Debug)]
 pub enum ArchiveError {
@@ -12,6 +17,13 @@ pub enum ArchiveError {
     UnsupportedFormat(String),
     InvalidArchive(String),
     FileNotFound(String), // New variant for missing file
+    InsufficientSpace { required: u64, available: u64 },
+    /// A volume of a multi-volume archive is missing next to the first one.
+    MissingVolume(String),
+    /// The archive is encrypted and no password was given.
+    PasswordRequired,
+    /// None of the given passwords opened the archive.
+    WrongPassword,
 }
 
 impl fmt::Display for ArchiveError {
@@ -21,6 +33,17 @@ impl fmt::Display for ArchiveError {
             ArchiveError::UnsupportedFormat(err) => write!(f, "Unsupported format: {}", err),
             ArchiveError::InvalidArchive(err) => write!(f, "Invalid archive: {}", err),
             ArchiveError::FileNotFound(err) => write!(f, "File not found: {}", err),
+            ArchiveError::InsufficientSpace {
+                required,
+                available,
+            } => write!(
+                f,
+                "Not enough disk space to extract: {} bytes needed, {} bytes available",
+                required, available
+            ),
+            ArchiveError::MissingVolume(err) => write!(f, "Missing archive volume: {}", err),
+            ArchiveError::PasswordRequired => write!(f, "Archive is password protected"),
+            ArchiveError::WrongPassword => write!(f, "Wrong archive password"),
         }
     }
 }
@@ -35,17 +58,83 @@ impl From<std::io::Error> for ArchiveError {
 
 impl From<zip::result::ZipError> for ArchiveError {
     fn from(err: zip::result::ZipError) -> Self {
-        ArchiveError::InvalidArchive(err.to_string())
+        match err {
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => {
+                ArchiveError::PasswordRequired
+            }
+            ZipError::InvalidPassword => ArchiveError::WrongPassword,
+            err => ArchiveError::InvalidArchive(err.to_string()),
+        }
+    }
+}
+
+impl From<sevenz_rust::Error> for ArchiveError {
+    fn from(err: sevenz_rust::Error) -> Self {
+        match err {
+            sevenz_rust::Error::PasswordRequired => ArchiveError::PasswordRequired,
+            sevenz_rust::Error::MaybeBadPassword(_) => ArchiveError::WrongPassword,
+            err => ArchiveError::InvalidArchive(err.to_string()),
+        }
+    }
+}
+
+impl From<UnrarError> for ArchiveError {
+    fn from(err: UnrarError) -> Self {
+        match (err.code, err.when) {
+            (Code::EOpen, When::Process) => ArchiveError::MissingVolume(err.to_string()),
+            (Code::EOpen, _) => ArchiveError::FileNotFound(err.to_string()),
+            (Code::MissingPassword, _) => ArchiveError::PasswordRequired,
+            (Code::BadPassword, _) => ArchiveError::WrongPassword,
+            (Code::ECreate | Code::EClose | Code::ERead | Code::EWrite | Code::NoMemory, _) => {
+                ArchiveError::Io(err.to_string())
+            }
+            _ => ArchiveError::InvalidArchive(err.to_string()),
+        }
+    }
+}
+
+/// How far an extraction has got, in bytes and entries of the archive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtractionProgress {
+    pub extracted_bytes: u64,
+    pub total_bytes: u64,
+    pub extracted_entries: usize,
+    pub total_entries: usize,
+    /// How much of the archive file has been read, for compressed streams whose unpacked size
+    /// and entry count aren't known until the end.
+    pub archive_position: u64,
+    pub archive_size: u64,
+}
+
+impl ExtractionProgress {
+    /// Percentage done, by bytes when the unpacked size is known, by how much of the archive
+    /// has been read for streams, and by entries otherwise.
+    pub fn percent(&self) -> f32 {
+        if self.total_bytes > 0 {
+            (self.extracted_bytes as f64 / self.total_bytes as f64 * 100.0) as f32
+        } else if self.archive_size > 0 {
+            (self.archive_position as f64 / self.archive_size as f64 * 100.0) as f32
+        } else if self.total_entries > 0 {
+            (self.extracted_entries as f64 / self.total_entries as f64 * 100.0) as f32
+        } else {
+            0.0
+        }
     }
 }
 
+/// Extracts `file_path` into `output_dir`, refusing up front when the unpacked files plus
+/// `min_free_space` bytes wouldn't fit on the output volume. An encrypted archive is tried with
+/// each of `passwords` in turn; it fails with `PasswordRequired` when there are none and with
+/// `WrongPassword` when none of them fit.
 pub fn unarchive_file_with_progress<F>(
     file_path: &str,
     output_dir: &str,
+    min_free_space: u64,
+    passwords: &[String],
     progress_callback: F,
 ) -> Result<(), ArchiveError>
 where
-    F: Fn(f32),
+    F: Fn(&ExtractionProgress),
 {
     let path = Path::new(file_path);
 
@@ -57,39 +146,198 @@ where
         )));
     }
 
-    let extension = path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| ext.to_lowercase())
-        .ok_or_else(|| ArchiveError::UnsupportedFormat("No file extension".to_string()))?;
+    let format = ArchiveFormat::detect(path)?;
 
+    ensure_space(path, format, Path::new(output_dir), min_free_space)?;
     fs::create_dir_all(output_dir)?;
 
-    match extension.as_str() {
-        "zip" => extract_zip(file_path, output_dir, progress_callback),
-        "7z" => extract_7z(file_path, output_dir, progress_callback),
-        "rar" => extract_rar(file_path, output_dir, progress_callback),
-        _ => Err(ArchiveError::UnsupportedFormat(format!(
-            "Unsupported file format: {}",
-            extension
-        ))),
+    let extract = |password: Option<&str>| match format {
+        ArchiveFormat::Zip => extract_zip(file_path, output_dir, password, &progress_callback),
+        ArchiveFormat::SevenZ => extract_7z(file_path, output_dir, password, &progress_callback),
+        ArchiveFormat::Rar => extract_rar(file_path, output_dir, password, &progress_callback),
+        ArchiveFormat::Tar(compression) => {
+            extract_tar(file_path, output_dir, compression, &progress_callback)
+        }
+    };
+
+    // Most archives aren't encrypted, so only go through the passwords once one is asked for.
+    match extract(None) {
+        Err(ArchiveError::PasswordRequired) if !passwords.is_empty() => {}
+        result => return result,
+    }
+    for password in passwords {
+        match extract(Some(password)) {
+            Err(ArchiveError::PasswordRequired | ArchiveError::WrongPassword) => {}
+            result => return result,
+        }
+    }
+    Err(ArchiveError::WrongPassword)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveFormat {
+    Zip,
+    SevenZ,
+    Rar,
+    Tar(Compression),
+}
+
+/// The compression around a tar stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+/// File name endings of each format. Compound ones come first, since `Path::extension` alone
+/// would only see the `gz` of `.tar.gz`.
+const FORMAT_SUFFIXES: &[(&str, ArchiveFormat)] = &[
+    (".tar.gz", ArchiveFormat::Tar(Compression::Gzip)),
+    (".tgz", ArchiveFormat::Tar(Compression::Gzip)),
+    (".tar.xz", ArchiveFormat::Tar(Compression::Xz)),
+    (".txz", ArchiveFormat::Tar(Compression::Xz)),
+    (".tar.bz2", ArchiveFormat::Tar(Compression::Bzip2)),
+    (".tbz2", ArchiveFormat::Tar(Compression::Bzip2)),
+    (".tbz", ArchiveFormat::Tar(Compression::Bzip2)),
+    (".tar.zst", ArchiveFormat::Tar(Compression::Zstd)),
+    (".tzst", ArchiveFormat::Tar(Compression::Zstd)),
+    (".tar", ArchiveFormat::Tar(Compression::None)),
+    (".zip", ArchiveFormat::Zip),
+    (".7z", ArchiveFormat::SevenZ),
+    (".rar", ArchiveFormat::Rar),
+];
+
+/// Leading bytes of each format. Hosts often serve files as `download` or `file.bin`, so these
+/// take precedence over the file name.
+const FORMAT_SIGNATURES: &[(&[u8], ArchiveFormat)] = &[
+    (b"PK\x03\x04", ArchiveFormat::Zip),
+    // An empty zip has nothing but the end of central directory record.
+    (b"PK\x05\x06", ArchiveFormat::Zip),
+    (b"7z\xBC\xAF\x27\x1C", ArchiveFormat::SevenZ),
+    // Shared by RAR 1.5-4.x (`\x00`) and RAR 5 (`\x01\x00`).
+    (b"Rar!\x1A\x07", ArchiveFormat::Rar),
+    (b"\x1F\x8B", ArchiveFormat::Tar(Compression::Gzip)),
+    (b"\xFD7zXZ\x00", ArchiveFormat::Tar(Compression::Xz)),
+    (b"BZh", ArchiveFormat::Tar(Compression::Bzip2)),
+    (b"\x28\xB5\x2F\xFD", ArchiveFormat::Tar(Compression::Zstd)),
+];
+
+/// Where a POSIX tar header keeps its `ustar` magic.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+impl ArchiveFormat {
+    /// The format of `path` by its signature, or by its name when the signature is unknown, such
+    /// as for old tar files without the `ustar` magic.
+    fn detect(path: &Path) -> Result<Self, ArchiveError> {
+        let mut header = Vec::with_capacity(TAR_MAGIC_OFFSET + 5);
+        File::open(path)?
+            .take(TAR_MAGIC_OFFSET as u64 + 5)
+            .read_to_end(&mut header)?;
+
+        Self::from_signature(&header)
+            .or_else(|| Self::from_path(path))
+            .ok_or_else(|| match path.extension() {
+                Some(extension) => ArchiveError::UnsupportedFormat(format!(
+                    "Unsupported file format: {}",
+                    extension.to_string_lossy().to_lowercase()
+                )),
+                None => ArchiveError::UnsupportedFormat("Unrecognized archive".to_string()),
+            })
+    }
+
+    fn from_signature(header: &[u8]) -> Option<Self> {
+        let tar_magic = header.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5);
+        FORMAT_SIGNATURES
+            .iter()
+            .find(|(signature, _)| header.starts_with(signature))
+            .map(|(_, format)| *format)
+            .or_else(|| {
+                (tar_magic == Some(b"ustar")).then_some(ArchiveFormat::Tar(Compression::None))
+            })
     }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        FORMAT_SUFFIXES
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, format)| *format)
+    }
+}
+
+fn ensure_space(
+    archive: &Path,
+    format: ArchiveFormat,
+    output_dir: &Path,
+    min_free_space: u64,
+) -> Result<(), ArchiveError> {
+    let Some(available) = crate::diskspace::available_space(output_dir) else {
+        return Ok(());
+    };
+    let required = unpacked_size(archive, format)?.saturating_add(min_free_space);
+    if available < required {
+        return Err(ArchiveError::InsufficientSpace {
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Total size of the archive's entries once extracted. Formats whose listing we can't read
+/// cheaply fall back to the archive's own size, which is a lower bound.
+fn unpacked_size(archive: &Path, format: ArchiveFormat) -> Result<u64, ArchiveError> {
+    let archive_len = fs::metadata(archive)?.len();
+    let size = match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipArchive::new(File::open(archive)?)?;
+            let mut total = 0u64;
+            for i in 0..zip.len() {
+                total = total.saturating_add(zip.by_index_raw(i)?.size());
+            }
+            total
+        }
+        ArchiveFormat::SevenZ => {
+            let mut file = File::open(archive)?;
+            // Archives with encrypted headers can't be listed without the password.
+            match sevenz_rust::Archive::read(&mut file, archive_len, &[]) {
+                Ok(listing) => listing.files.iter().map(|entry| entry.size()).sum(),
+                Err(_) => archive_len,
+            }
+        }
+        // Archives with encrypted headers can't be listed here either.
+        ArchiveFormat::Rar => rar_listing(archive, None).map_or(archive_len, |(total, _)| total),
+        // Compressed tar streams would have to be decompressed to be listed.
+        ArchiveFormat::Tar(_) => archive_len,
+    };
+    Ok(size)
 }
 
 fn extract_zip<F>(
     file_path: &str,
     output_dir: &str,
+    password: Option<&str>,
     progress_callback: F,
 ) -> Result<(), ArchiveError>
 where
-    F: Fn(f32),
+    F: Fn(&ExtractionProgress),
 {
     let file = File::open(file_path)?;
     let mut archive = ZipArchive::new(file)?;
-    let total_files = archive.len() as f32;
+    let mut total_bytes = 0u64;
+    for i in 0..archive.len() {
+        total_bytes = total_bytes.saturating_add(archive.by_index_raw(i)?.size());
+    }
+    let mut reporter = ProgressReporter::new(total_bytes, archive.len(), progress_callback);
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+        let mut file = match password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes())?,
+            None => archive.by_index(i)?,
+        };
         let file_path = file.enclosed_name().ok_or_else(|| {
             ArchiveError::InvalidArchive("Invalid file path in archive".to_string())
         })?;
@@ -101,52 +349,385 @@ where
             if let Some(parent) = output_path.parent() {
                 fs::create_dir_all(parent)?;
             }
+            let encrypted = file.encrypted();
             let mut outfile = File::create(&output_path)?;
-            io::copy(&mut file, &mut outfile)?;
+            reporter.copy_entry(&mut file, &mut outfile, encrypted)?;
         }
-
-        // Report progress
-        let progress = ((i + 1) as f32 / total_files) * 100.0;
-        progress_callback(progress);
+        reporter.finish_entry();
     }
+    reporter.finish();
     Ok(())
 }
 
+/// Decodes the archive entry by entry so progress can be reported while large solid blocks are
+/// unpacked. Entry names are checked the same way `ZipFile::enclosed_name` checks zip entries.
 fn extract_7z<F>(
     file_path: &str,
     output_dir: &str,
+    password: Option<&str>,
     progress_callback: F,
 ) -> Result<(), ArchiveError>
 where
-    F: Fn(f32),
+    F: Fn(&ExtractionProgress),
 {
-    // sevenz_rust does not support progress callbacks directly
-    progress_callback(0.0);
-    decompress_file(file_path, output_dir)
-        .map_err(|e| ArchiveError::InvalidArchive(e.to_string()))?;
-    progress_callback(100.0);
+    let password = password.map_or_else(Password::empty, Password::from);
+    // Without per-entry encryption flags, every entry counts as encrypted once a password is set.
+    let encrypted = !password.is_empty();
+    let mut archive = SevenZReader::open(file_path, password)?;
+    let entries = &archive.archive().files;
+    let total_bytes = entries.iter().map(|entry| entry.size()).sum();
+    let mut reporter = ProgressReporter::new(total_bytes, entries.len(), progress_callback);
+
+    // The entry callback can only fail with the library's error type; keep ours aside instead.
+    let mut failure = None;
+    archive
+        .for_each_entries(|entry, data| {
+            let result = enclosed_name(entry.name())
+                .ok_or_else(|| {
+                    ArchiveError::InvalidArchive("Invalid file path in archive".to_string())
+                })
+                .and_then(|name| {
+                    let output_path = Path::new(output_dir).join(name);
+                    if entry.is_directory() {
+                        fs::create_dir_all(&output_path)?;
+                    } else {
+                        if let Some(parent) = output_path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        let mut outfile = BufWriter::new(File::create(&output_path)?);
+                        reporter.copy_entry(data, &mut outfile, encrypted)?;
+                        outfile.flush()?;
+                    }
+                    Ok(())
+                });
+            match result {
+                Ok(()) => {
+                    reporter.finish_entry();
+                    Ok(true)
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    Ok(false)
+                }
+            }
+        })?;
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    reporter.finish();
     Ok(())
 }
 
+/// Streams the archive through its decompressor in one pass. Regular files are written here so
+/// their progress can be reported and keep their unix permission bits; directories and links
+/// are left to the tar crate, which refuses to place them outside `output_dir`.
+fn extract_tar<F>(
+    file_path: &str,
+    output_dir: &str,
+    compression: Compression,
+    progress_callback: F,
+) -> Result<(), ArchiveError>
+where
+    F: Fn(&ExtractionProgress),
+{
+    let file = File::open(file_path)?;
+    let archive_size = file.metadata()?.len();
+    let position = Rc::new(Cell::new(0));
+    let reader = BufReader::new(CountingReader {
+        inner: file,
+        position: Rc::clone(&position),
+    });
+    let decoder: Box<dyn Read> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+    };
+    let mut reporter = ProgressReporter::streaming(archive_size, position, progress_callback);
+
+    let output_dir = Path::new(output_dir);
+    let root = output_dir.canonicalize()?;
+    let mut archive = tar::Archive::new(decoder);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let name = enclosed_name(&entry.path()?.to_string_lossy()).ok_or_else(|| {
+                ArchiveError::InvalidArchive("Invalid file path in archive".to_string())
+            })?;
+            let output_path = output_dir.join(name);
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+                // A link unpacked earlier could point the parent somewhere else.
+                if !parent.canonicalize()?.starts_with(&root) {
+                    return Err(ArchiveError::InvalidArchive(
+                        "Invalid file path in archive".to_string(),
+                    ));
+                }
+            }
+            // `File::create` would follow a link an earlier entry left at this path, so the old
+            // file is removed and a fresh one created in its place.
+            match fs::symlink_metadata(&output_path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    return Err(ArchiveError::InvalidArchive(
+                        "Invalid file path in archive".to_string(),
+                    ))
+                }
+                Ok(_) => fs::remove_file(&output_path)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            let mode = entry.header().mode();
+            let outfile = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&output_path)?;
+            let mut outfile = BufWriter::new(outfile);
+            reporter.copy(&mut entry, &mut outfile)?;
+            outfile.flush()?;
+            drop(outfile);
+            if let Ok(mode) = mode {
+                set_unix_mode(&output_path, mode)?;
+            }
+        } else if !entry.unpack_in(output_dir)? {
+            return Err(ArchiveError::InvalidArchive(
+                "Invalid file path in archive".to_string(),
+            ));
+        }
+        reporter.finish_entry();
+    }
+    reporter.finish();
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_unix_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_unix_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Counts the bytes read from the archive file, to tell how far a stream has got.
+struct CountingReader<R> {
+    inner: R,
+    position: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position.set(self.position.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// `name` as a path relative to the output directory, or `None` when it is absolute or climbs
+/// out of it with `..`.
+fn enclosed_name(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+/// Tracks an extraction and hands the progress to the callback at most once per
+/// `PROGRESS_INTERVAL`, so archives with thousands of small files don't flood the frontend.
+struct ProgressReporter<F> {
+    progress: ExtractionProgress,
+    /// Where a streamed archive's reader is, if the progress is measured that way.
+    position: Option<Rc<Cell<u64>>>,
+    last_report: Instant,
+    callback: F,
+}
+
+impl<F> ProgressReporter<F>
+where
+    F: Fn(&ExtractionProgress),
+{
+    fn new(total_bytes: u64, total_entries: usize, callback: F) -> Self {
+        let progress = ExtractionProgress {
+            total_bytes,
+            total_entries,
+            ..Default::default()
+        };
+        callback(&progress);
+        Self {
+            progress,
+            position: None,
+            last_report: Instant::now(),
+            callback,
+        }
+    }
+
+    /// For archives that can only be read front to back: progress follows `position` through
+    /// the `archive_size` bytes of the archive file.
+    fn streaming(archive_size: u64, position: Rc<Cell<u64>>, callback: F) -> Self {
+        let progress = ExtractionProgress {
+            archive_size,
+            ..Default::default()
+        };
+        callback(&progress);
+        Self {
+            progress,
+            position: Some(position),
+            last_report: Instant::now(),
+            callback,
+        }
+    }
+
+    /// Copies one entry's data, counting the bytes as they are written.
+    fn copy(&mut self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<()> {
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            writer.write_all(&buffer[..read])?;
+            self.add_bytes(read as u64);
+        }
+    }
+
+    /// Copies an archive entry. When reading an `encrypted` entry fails, the password is taken
+    /// to be wrong, since decrypting with the wrong key yields data that doesn't decompress or
+    /// fails its checksum.
+    fn copy_entry(
+        &mut self,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        encrypted: bool,
+    ) -> Result<(), ArchiveError> {
+        let mut reader = ReadErrors {
+            inner: reader,
+            failed: false,
+        };
+        match self.copy(&mut reader, writer) {
+            Ok(()) => Ok(()),
+            Err(_) if encrypted && reader.failed => Err(ArchiveError::WrongPassword),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn add_bytes(&mut self, bytes: u64) {
+        self.progress.extracted_bytes += bytes;
+        self.report_if_due();
+    }
+
+    fn finish_entry(&mut self) {
+        self.progress.extracted_entries += 1;
+        self.report_if_due();
+    }
+
+    /// Reports the final state, which the interval may have held back.
+    fn finish(&mut self) {
+        // A tar reader stops before the end-of-archive padding, so the position may fall short.
+        self.position = None;
+        self.progress.archive_position = self.progress.archive_size;
+        self.report();
+    }
+
+    fn report_if_due(&mut self) {
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        if let Some(position) = &self.position {
+            self.progress.archive_position = position.get().min(self.progress.archive_size);
+        }
+        (self.callback)(&self.progress);
+    }
+}
+
+/// Extracts in-process through the RAR library, which handles RAR5 and follows the volumes of a
+/// multi-volume archive starting from its first part, whichever part was picked. Progress is
+/// reported per entry, since the library writes each entry in one go.
 fn extract_rar<F>(
     file_path: &str,
     output_dir: &str,
+    password: Option<&str>,
     progress_callback: F,
 ) -> Result<(), ArchiveError>
 where
-    F: Fn(f32),
+    F: Fn(&ExtractionProgress),
 {
-    fs::create_dir_all(output_dir)?;
-    progress_callback(0.0);
-    let status = Command::new("unrar")
-        .args(&["x", file_path, output_dir])
-        .status()?;
-    if status.success() {
-        progress_callback(100.0);
-        Ok(())
-    } else {
-        Err(ArchiveError::InvalidArchive(
-            "RAR extraction failed".to_string(),
-        ))
-    }
-}
\ No newline at end of file
+    let (total_bytes, total_entries) = rar_listing(Path::new(file_path), password)?;
+    let mut reporter = ProgressReporter::new(total_bytes, total_entries, progress_callback);
+
+    let mut archive = rar_archive(Path::new(file_path), password)
+        .as_first_part()
+        .open_for_processing()?;
+    while let Some(entry) = archive.read_header()? {
+        let header = entry.entry();
+        let name = enclosed_name(&header.filename.to_string_lossy()).ok_or_else(|| {
+            ArchiveError::InvalidArchive("Invalid file path in archive".to_string())
+        })?;
+        let output_path = Path::new(output_dir).join(name);
+        let size = header.unpacked_size;
+
+        archive = if header.is_directory() {
+            fs::create_dir_all(&output_path)?;
+            entry.skip()?
+        } else {
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let encrypted = header.is_encrypted();
+            entry.extract_to(&output_path).map_err(|e| match e.code {
+                // RAR4 has no password check; a wrong one only shows up as corrupt data.
+                Code::BadData if encrypted && password.is_some() => ArchiveError::WrongPassword,
+                _ => e.into(),
+            })?
+        };
+        reporter.add_bytes(size);
+        reporter.finish_entry();
+    }
+    reporter.finish();
+    Ok(())
+}
+
+fn rar_archive<'a>(archive: &'a Path, password: Option<&'a str>) -> unrar::Archive<'a> {
+    match password {
+        Some(password) => unrar::Archive::with_password(archive, password),
+        None => unrar::Archive::new(archive),
+    }
+}
+
+/// Unpacked size and entry count of a RAR archive, across all of its volumes.
+fn rar_listing(archive: &Path, password: Option<&str>) -> Result<(u64, usize), ArchiveError> {
+    let mut total_bytes = 0u64;
+    let mut total_entries = 0;
+    for header in rar_archive(archive, password).as_first_part().open_for_listing()? {
+        total_bytes = total_bytes.saturating_add(header?.unpacked_size);
+        total_entries += 1;
+    }
+    Ok((total_bytes, total_entries))
+}
+
+/// Remembers whether reading failed, to tell corrupt data from a failed write.
+struct ReadErrors<'a> {
+    inner: &'a mut dyn Read,
+    failed: bool,
+}
+
+impl Read for ReadErrors<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        self.failed |= result.is_err();
+        result
+    }
+}
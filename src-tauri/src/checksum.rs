@@ -0,0 +1,58 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct FileDigests {
+    pub sha1: String,
+    pub sha256: String,
+}
+
+/// Computes SHA-1 and SHA-256 in a single pass, reading the file in fixed-size blocks so large
+/// archives are never held in memory.
+pub fn hash_file(path: &Path) -> io::Result<FileDigests> {
+    let mut file = File::open(path)?;
+    let mut sha1 = Sha1::new();
+    let mut sha256 = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha1.update(&buffer[..read]);
+        sha256.update(&buffer[..read]);
+    }
+
+    Ok(FileDigests {
+        sha1: hex::encode(sha1.finalize()),
+        sha256: hex::encode(sha256.finalize()),
+    })
+}
+
+/// Compares an expected checksum against the computed digests. The expected value may carry an
+/// algorithm prefix (`sha1:...`, `sha256:...`); without one the algorithm is inferred from the
+/// length of the hex string.
+pub fn verify(expected: &str, digests: &FileDigests) -> Result<bool, String> {
+    let expected = expected.trim();
+    let (algorithm, value) = match expected.split_once(':') {
+        Some((algorithm, value)) => (algorithm.trim().to_lowercase().replace('-', ""), value.trim()),
+        None => {
+            let algorithm = match expected.len() {
+                40 => "sha1",
+                64 => "sha256",
+                _ => return Err(format!("Unrecognized checksum format: {}", expected)),
+            };
+            (algorithm.to_string(), expected)
+        }
+    };
+
+    match algorithm.as_str() {
+        "sha1" => Ok(digests.sha1.eq_ignore_ascii_case(value)),
+        "sha256" => Ok(digests.sha256.eq_ignore_ascii_case(value)),
+        _ => Err(format!("Unsupported checksum algorithm: {}", algorithm)),
+    }
+}
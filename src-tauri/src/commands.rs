@@ -1,17 +1,15 @@
 use crate::downloadmanager;
-use crate::state::{save_state_to_file, AppState, ArticleResponse, DownloadedGameInfo, LaunchConfig};
+use crate::state::{
+    save_state_to_file, AppState, ArticleResponse, DownloadHostRules, DownloadedGameInfo, LaunchConfig,
+    ProxySettings,
+};
 use crate::types::{ActiveDownloads, DownloadInfo, PluginManifest, PluginRegistry};
 use crate::utils::{is_path_directory, path_exists};
-use futures_util::StreamExt;
-use reqwest::Client;
 use std::fs;
 use std::path::Path;
 use std::process::Command as StdCommand;
 use std::sync::{Mutex, RwLock};
-use tauri::{AppHandle, Emitter, Manager, State};
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
-use tokio_util::sync::CancellationToken;
+use tauri::{AppHandle, Manager, State};
 
 // Basic utility commands
 #[tauri::command]
@@ -184,10 +182,11 @@ pub fn set_download_dir(
 // Article commands
 #[tauri::command]
 pub async fn fetch_article_by_slug(
+    app: AppHandle,
     slug: String,
     token: Option<String>,
 ) -> Result<ArticleResponse, String> {
-    crate::state::fetch_article_by_slug(slug, token).await
+    crate::state::fetch_article_by_slug(&app, slug, token).await
 }
 // Plugin commands
 #[tauri::command]
@@ -231,210 +230,393 @@ pub fn remove_plugin(
 }
 
 // Download commands
+/// Queues a download; it starts as soon as fewer than the configured maximum are running.
+/// `checksum` (`sha1:<hex>`, `sha256:<hex>` or bare hex) is verified once the file is complete.
 #[tauri::command]
 pub async fn download_file(
     app: AppHandle,
     download_id: String,
     url: String,
     filename: String,
+    priority: Option<i32>,
+    checksum: Option<String>,
+) -> Result<(), String> {
+    downloadmanager::start_download(
+        &app,
+        DownloadInfo {
+            id: download_id,
+            filename,
+            url,
+            priority: priority.unwrap_or(0),
+            expected_checksum: checksum,
+            ..Default::default()
+        },
+    )
+}
+
+/// Downloads one file from an ordered list of mirrors, e.g. the MEGA, MediaFire and direct links
+/// of an article, moving on to the next one when a mirror fails.
+#[tauri::command]
+pub fn download_from_mirrors(
+    app: AppHandle,
+    download_id: String,
+    urls: Vec<String>,
+    filename: String,
+    priority: Option<i32>,
+    checksum: Option<String>,
+) -> Result<(), String> {
+    let url = urls.first().cloned().ok_or("No download URL given")?;
+    downloadmanager::start_download(
+        &app,
+        DownloadInfo {
+            id: download_id,
+            filename,
+            url,
+            mirrors: urls,
+            priority: priority.unwrap_or(0),
+            expected_checksum: checksum,
+            ..Default::default()
+        },
+    )
+}
+
+/// Fetches an article's download links and starts one download per file, trying the providers
+/// in `providers` order first. Uses the signed-in user's token unless one is given. Returns the
+/// ids of the new downloads.
+#[tauri::command]
+pub async fn download_article(
+    app: AppHandle,
+    article_id: i64,
+    article_slug: Option<String>,
+    token: Option<String>,
+    providers: Option<Vec<String>>,
+    priority: Option<i32>,
+) -> Result<Vec<String>, String> {
+    let token = token.or_else(|| {
+        let state = app.state::<Mutex<AppState>>();
+        let token = state.lock().ok().and_then(|app_state| app_state.token.clone());
+        token
+    });
+    let links = crate::api::fetch_article_downloads(&app, article_id, token.as_deref()).await?;
+    downloadmanager::start_article_download(
+        &app,
+        article_id,
+        article_slug,
+        &links,
+        &providers.unwrap_or_default(),
+        priority.unwrap_or(0),
+    )
+}
+
+/// Stops a running download. The partial file is deleted unless `keep_partial` is set.
+#[tauri::command]
+pub fn cancel_download(
+    app: AppHandle,
+    download_id: String,
+    keep_partial: Option<bool>,
+) -> Result<(), String> {
+    crate::downloads::cancel_download(&app, &download_id, keep_partial.unwrap_or(false))
+}
+
+/// Same as `cancel_download`; the downloads page invokes it under this name.
+#[tauri::command]
+pub fn cancel_active_download(
+    app: AppHandle,
+    download_id: String,
+    keep_partial: Option<bool>,
 ) -> Result<(), String> {
-    let host = url::Url::parse(&url)
-        .map_err(|e| e.to_string())?
-        .host_str()
-        .ok_or("No host in URL")?
-        .to_string();
-
-    let plugin_registry = app.state::<Mutex<PluginRegistry>>();
-    let registry = plugin_registry.lock().map_err(|e| e.to_string())?;
-    let plugin = registry
-        .find_plugin_for_action("download", Some(&host))
-        .cloned();
-
-    if let Some(plugin) = plugin {
-        // Plugin found, use it
-        let cancel_token = CancellationToken::new();
-        let input = serde_json::json!({ "url": url });
-
-        let app_clone = app.clone();
-        let download_id_clone = download_id.clone();
-
-        app.emit(
-            "download://progress",
-            serde_json::json!({
-                "downloadId": download_id,
-                "status": "starting",
-                "progress": 0,
-            }),
-        )
-        .map_err(|e| e.to_string())?;
-
-        tokio::spawn(async move {
-            let result = downloadmanager::execute_plugin(&plugin, "download", input, &app_clone, cancel_token).await;
-
-            match result {
-                Ok(downloaded_path) => {
-                    app_clone
-                        .emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "completed",
-                                "progress": 100,
-                                "path": downloaded_path,
-                            }),
-                        )
-                        .ok();
-                }
-                Err(e) => {
-                    app_clone
-                        .emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "failed",
-                                "error": format!("{:?}", e),
-                            }),
-                        )
-                        .ok();
-                }
-            }
-        });
-use futures_util::StreamExt;
-use reqwest::Client;
-use std::io::Write;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
-
-// ... imports
-
-// ... in download_file function
-    } else {
-        // No plugin found, handle as a direct download or error out
-        if host.ends_with("chanomhub.online") {
-            let app_clone = app.clone();
-            let download_id_clone = download_id.clone();
-
-            tokio::spawn(async move {
-                let download_dir = match get_download_dir(app_clone.clone()) {
-                    Ok(dir) => dir,
-                    Err(e) => {
-                        app_clone.emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "failed",
-                                "error": e,
-                            }),
-                        ).ok();
-                        return;
-                    }
-                };
-
-                let file_path = std::path::Path::new(&download_dir).join(&filename);
-
-                let client = Client::new();
-                let res = match client.get(&url).send().await {
-                    Ok(res) => res,
-                    Err(e) => {
-                        app_clone.emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "failed",
-                                "error": e.to_string(),
-                            }),
-                        ).ok();
-                        return;
-                    }
-                };
-
-                let total_size = res.content_length().unwrap_or(0);
-                let mut file = match File::create(&file_path).await {
-                    Ok(file) => file,
-                    Err(e) => {
-                        app_clone.emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "failed",
-                                "error": e.to_string(),
-                            }),
-                        ).ok();
-                        return;
-                    }
-                };
-
-                let mut stream = res.bytes_stream();
-                let mut downloaded: u64 = 0;
-
-                while let Some(item) = stream.next().await {
-                    let chunk = match item {
-                        Ok(chunk) => chunk,
-                        Err(e) => {
-                            app_clone.emit(
-                                "download://progress",
-                                serde_json::json!({
-                                    "downloadId": download_id_clone,
-                                    "status": "failed",
-                                    "error": e.to_string(),
-                                }),
-                            ).ok();
-                            return;
-                        }
-                    };
-                    if let Err(e) = file.write_all(&chunk).await {
-                        app_clone.emit(
-                            "download://progress",
-                            serde_json::json!({
-                                "downloadId": download_id_clone,
-                                "status": "failed",
-                                "error": e.to_string(),
-                            }),
-                        ).ok();
-                        return;
-                    }
-                    downloaded += chunk.len() as u64;
-                    let progress = if total_size > 0 {
-                        (downloaded as f32 / total_size as f32) * 100.0
-                    } else {
-                        0.0 // Indeterminate
-                    };
-                    app_clone.emit(
-                        "download://progress",
-                        serde_json::json!({
-                            "downloadId": download_id_clone,
-                            "status": "downloading",
-                            "progress": progress,
-                        }),
-                    ).ok();
-                }
-
-                app_clone.emit(
-                    "download://progress",
-                    serde_json::json!({
-                        "downloadId": download_id_clone,
-                        "status": "completed",
-                        "progress": 100.0,
-                        "path": file_path.to_str().unwrap(),
-                    }),
-                ).ok();
-            });
-        } else {
-            return Err(format!("Plugin not found for provider: {}", host));
-        }
+    crate::downloads::cancel_download(&app, &download_id, keep_partial.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn pause_download(app: AppHandle, download_id: String) -> Result<(), String> {
+    crate::downloads::pause_download(&app, &download_id)
+}
+
+/// Puts a paused (or failed) download back in the queue under the same id. Direct downloads
+/// continue from their `.part` file; plugin downloads are handed the same `file_path` again.
+#[tauri::command]
+pub fn resume_download(app: AppHandle, download_id: String) -> Result<(), String> {
+    let download = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let downloads = active_downloads
+            .read()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        downloads
+            .downloads
+            .get(&download_id)
+            .cloned()
+            .ok_or_else(|| format!("Download not found: {}", download_id))?
+    };
+
+    if download.status.is_downloaded() {
+        return Err(format!("Download already completed: {}", download_id));
     }
 
+    downloadmanager::enqueue_download(&app, download)
+}
+
+#[tauri::command]
+pub fn set_download_priority(app: AppHandle, download_id: String, priority: i32) -> Result<(), String> {
+    downloadmanager::set_priority(&app, &download_id, priority)?;
+    downloadmanager::process_queue(&app);
+    Ok(())
+}
+
+/// Reorders queued downloads to match `download_ids`, first to last.
+#[tauri::command]
+pub fn reorder_download_queue(app: AppHandle, download_ids: Vec<String>) -> Result<(), String> {
+    downloadmanager::reorder_queue(&app, &download_ids)
+}
+
+#[tauri::command]
+pub fn get_max_concurrent_downloads(state: State<'_, Mutex<AppState>>) -> Result<usize, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state
+        .max_concurrent_downloads
+        .unwrap_or(downloadmanager::DEFAULT_MAX_CONCURRENT_DOWNLOADS))
+}
+
+#[tauri::command]
+pub fn set_max_concurrent_downloads(
+    max: usize,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if max == 0 {
+        return Err("Maximum concurrent downloads must be at least 1".to_string());
+    }
+    {
+        let mut app_state = state
+            .lock()
+            .map_err(|e| format!("Failed to lock state: {}", e))?;
+        app_state.max_concurrent_downloads = Some(max);
+        save_state_to_file(&app, &app_state)?;
+    }
+    // A higher limit may free slots for queued downloads.
+    downloadmanager::process_queue(&app);
+    Ok(())
+}
+
+/// Global bandwidth cap in bytes per second; `None` means unlimited.
+#[tauri::command]
+pub fn get_global_speed_limit(state: State<'_, Mutex<AppState>>) -> Result<Option<u64>, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state.max_download_speed)
+}
+
+#[tauri::command]
+pub fn set_global_speed_limit(
+    bytes_per_second: Option<u64>,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.max_download_speed = bytes_per_second.filter(|limit| *limit > 0);
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Caps a single download in bytes per second; `None` removes the cap. Running direct downloads
+/// pick the change up on their next chunk, plugin downloads the next time they start.
+#[tauri::command]
+pub fn set_download_speed_limit(
+    app: AppHandle,
+    download_id: String,
+    bytes_per_second: Option<u64>,
+) -> Result<(), String> {
+    crate::downloads::update_download(&app, &download_id, true, |download| {
+        download.speed_limit = bytes_per_second.filter(|limit| *limit > 0);
+    })
+}
+
+/// Connections used per direct download; 1 disables segmented downloads.
+#[tauri::command]
+pub fn get_download_connections(state: State<'_, Mutex<AppState>>) -> Result<u32, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state
+        .download_connections
+        .unwrap_or(crate::httpdownload::DEFAULT_CONNECTIONS))
+}
+
+#[tauri::command]
+pub fn set_download_connections(
+    connections: u32,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if !(1..=crate::httpdownload::MAX_CONNECTIONS).contains(&connections) {
+        return Err(format!(
+            "Connections must be between 1 and {}",
+            crate::httpdownload::MAX_CONNECTIONS
+        ));
+    }
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.download_connections = Some(connections);
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Automatic retries per download after transient failures; 0 disables retrying.
+#[tauri::command]
+pub fn get_max_download_retries(state: State<'_, Mutex<AppState>>) -> Result<u32, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state
+        .max_download_retries
+        .unwrap_or(downloadmanager::DEFAULT_MAX_RETRIES))
+}
+
+#[tauri::command]
+pub fn set_max_download_retries(
+    retries: u32,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.max_download_retries = Some(retries);
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Free space in bytes kept on the download volume; running downloads pause below it.
+#[tauri::command]
+pub fn get_min_free_disk_space(state: State<'_, Mutex<AppState>>) -> Result<u64, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state
+        .min_free_disk_space
+        .unwrap_or(crate::diskspace::DEFAULT_MIN_FREE_SPACE))
+}
+
+#[tauri::command]
+pub fn set_min_free_disk_space(
+    bytes: u64,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.min_free_disk_space = Some(bytes);
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Hosts downloaded without a plugin, hosts that are never downloaded from, and whether any other
+/// http(s) host may be used.
+#[tauri::command]
+pub fn get_download_host_rules(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<DownloadHostRules, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state.download_hosts.clone())
+}
+
+#[tauri::command]
+pub fn set_download_host_rules(
+    rules: DownloadHostRules,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.download_hosts = rules;
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Records the webview's `navigator.userAgent`, sent with downloads intercepted from it.
+#[tauri::command]
+pub fn set_webview_user_agent(
+    user_agent: String,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.webview_user_agent = Some(user_agent);
+    Ok(())
+}
+
+/// Proxy used for API requests, downloads and plugins.
+#[tauri::command]
+pub fn get_proxy_settings(state: State<'_, Mutex<AppState>>) -> Result<ProxySettings, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state.proxy.clone())
+}
+
+#[tauri::command]
+pub fn set_proxy_settings(
+    settings: ProxySettings,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    crate::proxy::validate(&settings)?;
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.proxy = settings;
+    save_state_to_file(&app, &app_state)?;
+    Ok(())
+}
+
+/// Passwords tried on encrypted archives when the ones given for the archive don't fit.
+#[tauri::command]
+pub fn get_archive_passwords(state: State<'_, Mutex<AppState>>) -> Result<Vec<String>, String> {
+    let app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    Ok(app_state.archive_passwords.clone())
+}
+
+#[tauri::command]
+pub fn set_archive_passwords(
+    passwords: Vec<String>,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut app_state = state
+        .lock()
+        .map_err(|e| format!("Failed to lock state: {}", e))?;
+    app_state.archive_passwords = passwords;
+    save_state_to_file(&app, &app_state)?;
     Ok(())
 }
 
+/// Extracts a finished download. `password` and `passwords`, e.g. the ones listed in the
+/// article, are tried on an encrypted archive before the saved archive passwords.
 #[tauri::command]
 pub async fn unarchive_file(
     file_path: String,
     output_dir: String,
     download_id: String,
+    password: Option<String>,
+    passwords: Option<Vec<String>>,
     app: tauri::AppHandle,
 ) -> Result<(), String> {
-    crate::downloads::unarchive_file(file_path, output_dir, download_id, app).await
+    let mut candidates: Vec<String> = password.into_iter().collect();
+    candidates.extend(passwords.unwrap_or_default());
+    crate::downloads::unarchive_file(file_path, output_dir, download_id, candidates, app).await
 }
 
 #[tauri::command]
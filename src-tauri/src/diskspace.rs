@@ -0,0 +1,45 @@
+use crate::state::AppState;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sysinfo::Disks;
+use tauri::{AppHandle, Manager};
+
+/// Free space kept in reserve on the download volume unless configured otherwise.
+pub const DEFAULT_MIN_FREE_SPACE: u64 = 512 * 1024 * 1024;
+
+/// Bytes available to us on the volume holding `path`. `path` doesn't need to exist yet; its
+/// nearest existing ancestor is used. `None` when the volume can't be determined.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let path = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())?
+        .canonicalize()
+        .ok()?;
+    let path = strip_verbatim_prefix(path);
+
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Free space that downloads and extraction must leave on the volume, in bytes.
+pub fn min_free_space(app: &AppHandle) -> u64 {
+    let state = app.state::<Mutex<AppState>>();
+    let min_free = state
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.min_free_disk_space);
+    min_free.unwrap_or(DEFAULT_MIN_FREE_SPACE)
+}
+
+/// `canonicalize` returns `\\?\C:\...` on Windows, which never matches a mount point like `C:\`.
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+        Some(stripped) if !stripped.starts_with("UNC\\") => PathBuf::from(stripped),
+        _ => path,
+    }
+}
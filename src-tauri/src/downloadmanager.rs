@@ -1,8 +1,27 @@
 // downloadmanager.rs
+use crate::commands::get_download_dir;
+use crate::api::ArticleDownloadLink;
+use crate::checksum;
+use crate::diskspace;
+use crate::downloads::{
+    claim_file_path, complete_download, corrupt_download, discard_partial_download, fail_download,
+    finish_stopped_download,
+    progress_event, release_cancel_token, set_status, update_download,
+};
+use crate::filename;
+use crate::httpdownload::download_direct;
 use crate::plugin::{execute_plugin as plugin_execute_plugin};
-use crate::types::PluginManifest;
+use crate::state::{save_active_downloads_to_file, AppState};
+use crate::types::{
+    ActiveDownloads, BrowserContext, DownloadInfo, DownloadStatus, FailureStage, PluginManifest,
+    PluginRegistry,
+};
+use rand::Rng;
 use serde_json::Value;
-use tauri::AppHandle;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
 use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
@@ -10,6 +29,56 @@ pub enum DownloadError {
     InvalidUrl(String),
     UnsupportedProvider(String),
     DownloadFailed(String),
+    Cancelled,
+    /// The server ignored a `Range` request it was expected to honour.
+    RangesUnsupported,
+    /// A failure worth retrying, such as a dropped connection, a 5xx or a 429. `retry_after` is
+    /// the delay the server asked for, if any.
+    Transient {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// Checked before a transfer starts: the rest of the file plus the configured reserve doesn't
+    /// fit on the volume.
+    InsufficientSpace { required: u64, available: u64 },
+    /// Free space fell below the configured reserve while the transfer was running.
+    LowDiskSpace { available: u64 },
+    /// The server answered with a web page or a JSON document where a file was expected, as
+    /// hosts do for expired links. `title` is the page title or the start of the document.
+    NotAFile {
+        received: &'static str,
+        title: Option<String>,
+    },
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::InvalidUrl(url) => write!(f, "Invalid URL: {}", url),
+            DownloadError::UnsupportedProvider(host) => write!(f, "Unsupported provider: {}", host),
+            DownloadError::DownloadFailed(message) => f.write_str(message),
+            DownloadError::Cancelled => f.write_str("Download cancelled"),
+            DownloadError::RangesUnsupported => f.write_str("Server does not support byte ranges"),
+            DownloadError::Transient { message, .. } => f.write_str(message),
+            DownloadError::InsufficientSpace {
+                required,
+                available,
+            } => write!(
+                f,
+                "Not enough disk space: {} bytes needed, {} bytes available",
+                required, available
+            ),
+            DownloadError::LowDiskSpace { available } => write!(
+                f,
+                "Paused because free disk space dropped to {} bytes",
+                available
+            ),
+            DownloadError::NotAFile { received, title } => match title {
+                Some(title) => write!(f, "Expected a file but received {}: \"{}\"", received, title),
+                None => write!(f, "Expected a file but received {}", received),
+            },
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -17,6 +86,759 @@ struct DownloadResult {
     path: String,
 }
 
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Backoff before the first retry; doubled for every further attempt up to `MAX_RETRY_DELAY`.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Upper bound on a server's `Retry-After`, so a bogus value can't park a download for days.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+/// How a URL gets downloaded: through a plugin registered for its host, streamed directly from
+/// an allowed host, or streamed from any other http(s) host when that is enabled.
+pub enum DownloadHandler {
+    Plugin(Box<PluginManifest>),
+    Direct,
+    GenericHttp,
+}
+
+impl DownloadHandler {
+    pub fn provider(&self) -> String {
+        match self {
+            DownloadHandler::Plugin(plugin) => plugin.id.clone(),
+            DownloadHandler::Direct => "direct".to_string(),
+            DownloadHandler::GenericHttp => "http".to_string(),
+        }
+    }
+}
+
+pub fn resolve_handler(app: &AppHandle, url: &str) -> Result<DownloadHandler, String> {
+    let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("No host in URL")?.to_string();
+
+    let rules = {
+        let state = app.state::<Mutex<AppState>>();
+        let app_state = state.lock().map_err(|e| e.to_string())?;
+        app_state.download_hosts.clone()
+    };
+    if rules.is_blocked(&host) {
+        return Err(format!("Downloads from {} are blocked", host));
+    }
+
+    let plugin_registry = app.state::<Mutex<PluginRegistry>>();
+    let registry = plugin_registry.lock().map_err(|e| e.to_string())?;
+    if let Some(plugin) = registry.find_plugin_for_action("download", Some(&host)) {
+        return Ok(DownloadHandler::Plugin(Box::new(plugin.clone())));
+    }
+
+    // No plugin found, handle as a direct download or error out
+    let is_http = matches!(url.scheme(), "http" | "https");
+    if is_http && rules.is_allowed(&host) {
+        Ok(DownloadHandler::Direct)
+    } else if is_http && rules.generic_http {
+        Ok(DownloadHandler::GenericHttp)
+    } else {
+        Err(format!("Plugin not found for provider: {}", host))
+    }
+}
+
+/// Entry point for every new download, whether requested by the frontend or intercepted from the
+/// webview. With mirrors, the first URL a handler accepts is used. A download no handler accepts
+/// is still recorded, as failed, so it shows up in the history instead of vanishing.
+pub fn start_download(app: &AppHandle, mut download: DownloadInfo) -> Result<(), String> {
+    let mut last_error = None;
+    for url in candidate_urls(&download) {
+        match resolve_handler(app, &url) {
+            Ok(handler) => {
+                download.url = url;
+                download.provider = Some(handler.provider());
+                return enqueue_download(app, download);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    let e = last_error.unwrap_or_else(|| "No download URL given".to_string());
+    record_rejected_download(app, download, &e)?;
+    Err(e)
+}
+
+/// Starts a download intercepted from the webview. Direct transfers send `context` along, so
+/// hosts that depend on the browser session accept them.
+pub fn start_browser_download(
+    app: &AppHandle,
+    download: DownloadInfo,
+    context: BrowserContext,
+) -> Result<(), String> {
+    let download_id = download.id.clone();
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    active_downloads
+        .write()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?
+        .browser_contexts
+        .insert(download_id.clone(), context);
+
+    let result = start_download(app, download);
+    if result.is_err() {
+        if let Ok(mut downloads) = active_downloads.write() {
+            downloads.browser_contexts.remove(&download_id);
+        }
+    }
+    result
+}
+
+/// Links preferred when the caller doesn't name providers: our own direct downloads first.
+const DEFAULT_PROVIDER_PREFERENCE: &[&str] = &["direct"];
+
+/// Links of one file with their `provider_rank`.
+type RankedLinks<'a> = Vec<(usize, &'a ArticleDownloadLink)>;
+
+/// Starts an article's files. Links are mirrors of one download, ordered by `providers`: a
+/// preference matches a link whose host or name contains it, or `direct` for links we download
+/// ourselves. Only when the URLs name different files, as the parts of a split archive do, is each
+/// file downloaded on its own; links whose URL names no file are then kept together as one more
+/// download. Links no handler accepts are skipped. Returns the ids of the downloads that were
+/// started, in the order the article lists the files.
+pub fn start_article_download(
+    app: &AppHandle,
+    article_id: i64,
+    article_slug: Option<String>,
+    links: &[ArticleDownloadLink],
+    providers: &[String],
+    priority: i32,
+) -> Result<Vec<String>, String> {
+    // (file name from the URLs, ranked links), in the order the article lists the files. A link's
+    // `name` is only the provider's label, so it doesn't tell files apart.
+    let mut files: Vec<(Option<String>, RankedLinks)> = Vec::new();
+    for link in links.iter().filter(|link| link.is_available()) {
+        let Ok(handler) = resolve_handler(app, &link.url) else {
+            continue;
+        };
+        let rank = provider_rank(link, &handler, providers);
+        let name = url::Url::parse(&link.url)
+            .ok()
+            .and_then(|url| filename::file_name_in_url(&url));
+        let same_file = |file: &Option<String>| match (file, &name) {
+            (Some(file), Some(name)) => file.eq_ignore_ascii_case(name),
+            (file, name) => file.is_none() && name.is_none(),
+        };
+        match files.iter_mut().find(|(file, _)| same_file(file)) {
+            Some((_, mirrors)) => mirrors.push((rank, link)),
+            None => files.push((name, vec![(rank, link)])),
+        }
+    }
+    if files.is_empty() {
+        return Err(format!("Article {} has no downloadable links", article_id));
+    }
+    // A single named file: the unnamed links are most likely mirrors of it.
+    if files.len() == 2 {
+        if let Some(unnamed) = files.iter().position(|(file, _)| file.is_none()) {
+            let (_, mut mirrors) = files.remove(unnamed);
+            files[0].1.append(&mut mirrors);
+        }
+    }
+
+    let mut download_ids = Vec::new();
+    let mut errors = Vec::new();
+    for (name, mut ranked) in files {
+        // Stable, so links of equal rank keep the order the article lists them in.
+        ranked.sort_by_key(|(rank, _)| *rank);
+        let urls: Vec<String> = ranked.into_iter().map(|(_, link)| link.url.clone()).collect();
+        let filename = name
+            .or_else(|| article_slug.clone())
+            .map(|name| filename::sanitize(&name))
+            .unwrap_or_else(|| filename::FALLBACK_FILENAME.to_string());
+        let download_id = uuid::Uuid::new_v4().to_string();
+        let started = start_download(
+            app,
+            DownloadInfo {
+                id: download_id.clone(),
+                filename,
+                url: urls[0].clone(),
+                mirrors: urls,
+                priority,
+                article_id: Some(article_id),
+                article_slug: article_slug.clone(),
+                ..Default::default()
+            },
+        );
+        match started {
+            Ok(()) => download_ids.push(download_id),
+            Err(e) => errors.push(e),
+        }
+    }
+    // Downloads already queued stay queued; the others are only reported.
+    if download_ids.is_empty() {
+        return Err(errors.join("; "));
+    }
+    for e in errors {
+        eprintln!("Failed to start a download of article {}: {}", article_id, e);
+    }
+    Ok(download_ids)
+}
+
+fn provider_rank(link: &ArticleDownloadLink, handler: &DownloadHandler, providers: &[String]) -> usize {
+    let host = url::Url::parse(&link.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default();
+    let name = link.name.to_lowercase();
+    let matches = |preference: &str| {
+        let preference = preference.to_lowercase();
+        (preference == "direct"
+            && matches!(handler, DownloadHandler::Direct | DownloadHandler::GenericHttp))
+            || host.contains(&preference)
+            || name.contains(&preference)
+    };
+
+    let position = if providers.is_empty() {
+        DEFAULT_PROVIDER_PREFERENCE
+            .iter()
+            .position(|preference| matches(preference))
+    } else {
+        providers.iter().position(|preference| matches(preference))
+    };
+    position.unwrap_or(usize::MAX)
+}
+
+fn record_rejected_download(app: &AppHandle, mut download: DownloadInfo, error: &str) -> Result<(), String> {
+    let download_id = download.id.clone();
+    let event = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        if downloads.downloads.contains_key(&download_id) {
+            None
+        } else {
+            download.status = DownloadStatus::failed(error);
+            let event = progress_event(&download);
+            downloads.downloads.insert(download_id.clone(), download);
+            save_active_downloads_to_file(app, &downloads)?;
+            Some(event)
+        }
+    };
+
+    match event {
+        Some(event) => app
+            .emit("download://progress", event)
+            .map_err(|e| e.to_string()),
+        // A retry of a tracked download: keep its resume state and just mark it failed.
+        None => set_status(app, &download_id, DownloadStatus::failed(error), true, |_| {}),
+    }
+}
+
+/// Adds a download to the persistent queue and starts it if a slot is free. A download that is
+/// already tracked under the same id keeps its resume state and is simply re-queued.
+pub fn enqueue_download(app: &AppHandle, download: DownloadInfo) -> Result<(), String> {
+    let download_id = download.id.clone();
+    let event = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        if downloads.tokens.contains_key(&download_id) {
+            return Err(format!("Download is already running: {}", download_id));
+        }
+        let queue_position = next_queue_position(&downloads);
+        let entry = downloads
+            .downloads
+            .entry(download_id.clone())
+            .or_insert(download);
+        if entry.transition_to(DownloadStatus::Queued).is_err() {
+            return Err(format!("Download cannot be queued while {}: {}", entry.status, download_id));
+        }
+        entry.queue_position = queue_position;
+        entry.retry_count = 0;
+        let event = progress_event(entry);
+        save_active_downloads_to_file(app, &downloads)?;
+        event
+    };
+
+    app.emit("download://progress", event)
+        .map_err(|e| e.to_string())?;
+
+    process_queue(app);
+    Ok(())
+}
+
+fn next_queue_position(downloads: &ActiveDownloads) -> u64 {
+    downloads
+        .downloads
+        .values()
+        .filter(|download| download.status == DownloadStatus::Queued)
+        .map(|download| download.queue_position + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Assigns queue positions in the order of `download_ids`. Priority still takes precedence, so
+/// this only reorders downloads within the same priority.
+pub fn reorder_queue(app: &AppHandle, download_ids: &[String]) -> Result<(), String> {
+    {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        for (position, download_id) in download_ids.iter().enumerate() {
+            if let Some(download) = downloads.downloads.get_mut(download_id) {
+                download.queue_position = position as u64;
+            }
+        }
+        save_active_downloads_to_file(app, &downloads)?;
+    }
+
+    // The download now at the front starts right away if a slot is free.
+    process_queue(app);
+    Ok(())
+}
+
+pub fn set_priority(app: &AppHandle, download_id: &str, priority: i32) -> Result<(), String> {
+    {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        let download = downloads
+            .downloads
+            .get_mut(download_id)
+            .ok_or_else(|| format!("Download not found: {}", download_id))?;
+        download.priority = priority;
+        save_active_downloads_to_file(app, &downloads)?;
+    }
+
+    process_queue(app);
+    Ok(())
+}
+
+/// Bandwidth cap in bytes per second shared by all transfers, if one is configured.
+pub fn global_speed_limit(app: &AppHandle) -> Option<u64> {
+    let state = app.state::<Mutex<AppState>>();
+    let limit = state
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.max_download_speed);
+    limit.filter(|limit| *limit > 0)
+}
+
+/// The stricter of the global and the per-download limit, in bytes per second.
+fn effective_speed_limit(app: &AppHandle, download: &DownloadInfo) -> Option<u64> {
+    match (global_speed_limit(app), download.speed_limit.filter(|limit| *limit > 0)) {
+        (Some(global), Some(own)) => Some(global.min(own)),
+        (global, own) => global.or(own),
+    }
+}
+
+fn max_concurrent_downloads(app: &AppHandle) -> usize {
+    let state = app.state::<Mutex<AppState>>();
+    let max = state
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.max_concurrent_downloads);
+    max.unwrap_or(DEFAULT_MAX_CONCURRENT_DOWNLOADS).max(1)
+}
+
+/// Starts queued downloads, highest priority first, until the concurrency limit is reached.
+/// Hashes the downloads that were still being verified when the app last closed.
+pub fn resume_verifications(app: &AppHandle) {
+    let verifying: Vec<DownloadInfo> = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let Ok(downloads) = active_downloads.read() else {
+            return;
+        };
+        downloads
+            .downloads
+            .values()
+            .filter(|download| download.status == DownloadStatus::Verifying)
+            .cloned()
+            .collect()
+    };
+
+    for download in verifying {
+        // Entries saved before the final path was recorded still point at the partial file.
+        let path = download.path.clone().or_else(|| {
+            download
+                .part_path
+                .as_deref()
+                .and_then(|part_path| part_path.strip_suffix(".part"))
+                .map(|path| path.to_string())
+        });
+        let Some(path) = path.map(PathBuf::from).filter(|path| path.exists()) else {
+            let status = DownloadStatus::Failed {
+                stage: FailureStage::Verification,
+                error: "Downloaded file is missing".to_string(),
+            };
+            set_status(app, &download.id, status, true, |_| {}).ok();
+            continue;
+        };
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            verify_download(&app, &download, &path).await;
+        });
+    }
+}
+
+pub fn process_queue(app: &AppHandle) {
+    let max_concurrent = max_concurrent_downloads(app);
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let promoted = {
+        let Ok(mut downloads) = active_downloads.write() else {
+            return;
+        };
+        let free_slots = max_concurrent.saturating_sub(downloads.tokens.len());
+
+        let mut queued: Vec<(i32, u64, String)> = downloads
+            .downloads
+            .values()
+            .filter(|download| download.status == DownloadStatus::Queued)
+            .map(|download| (download.priority, download.queue_position, download.id.clone()))
+            .collect();
+        queued.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut promoted = Vec::new();
+        for (_, _, download_id) in queued.into_iter().take(free_slots) {
+            let Some(download) = downloads.downloads.get_mut(&download_id) else {
+                continue;
+            };
+            if let Err(e) = download.transition_to(DownloadStatus::Starting) {
+                eprintln!("{}", e);
+                continue;
+            }
+            let download = download.clone();
+            let token = CancellationToken::new();
+            downloads.tokens.insert(download_id, token.clone());
+            promoted.push((download, token));
+        }
+        if !promoted.is_empty() {
+            if let Err(e) = save_active_downloads_to_file(app, &downloads) {
+                eprintln!("Failed to save active downloads: {}", e);
+            }
+        }
+        promoted
+    };
+
+    for (download, cancel_token) in promoted {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            run_transfer(&app, &download, cancel_token).await;
+            release_cancel_token(&app, &download.id);
+            process_queue(&app);
+        });
+    }
+}
+
+async fn run_transfer(app: &AppHandle, download: &DownloadInfo, cancel_token: CancellationToken) {
+    let download_id = download.id.as_str();
+
+    let download_dir = match get_download_dir(app.clone()) {
+        Ok(dir) => dir,
+        Err(e) => {
+            fail_download(app, download_id, e);
+            return;
+        }
+    };
+    let download_dir = PathBuf::from(download_dir);
+
+    app.emit("download://progress", progress_event(download)).ok();
+
+    // A download that was paused or retried continues with the mirror it was using.
+    let candidates = candidate_urls(download);
+    let first = candidates
+        .iter()
+        .position(|url| *url == download.url)
+        .unwrap_or(0);
+    let mut last_error = None;
+
+    for (index, url) in candidates.iter().enumerate().skip(first) {
+        let is_last = index + 1 == candidates.len();
+        let handler = match resolve_handler(app, url) {
+            Ok(handler) => handler,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        if index != first {
+            eprintln!("Download {}: trying mirror {}", download_id, url);
+        }
+        let provider = handler.provider();
+        update_download(app, download_id, true, |download| {
+            download.url = url.clone();
+            download.provider = Some(provider);
+        })
+        .ok();
+
+        let (result, partial_file) =
+            transfer_from(app, download, url, handler, &download_dir, cancel_token.clone()).await;
+        match result {
+            Ok(path) => return verify_download(app, download, &path).await,
+            Err(DownloadError::Cancelled) => {
+                return finish_stopped_download(app, download_id, partial_file.as_deref())
+            }
+            Err(e @ DownloadError::LowDiskSpace { available }) => {
+                emit_disk_space_warning(app, download_id, None, available);
+                let result = set_status(app, download_id, DownloadStatus::Paused, true, |download| {
+                    download.last_error = Some(e.to_string());
+                });
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+                return;
+            }
+            Err(
+                e @ DownloadError::InsufficientSpace {
+                    required,
+                    available,
+                },
+            ) => {
+                emit_disk_space_warning(app, download_id, Some(required), available);
+                return fail_download(app, download_id, e.to_string());
+            }
+            Err(e) if !is_last => {
+                // Whatever the dead mirror left behind can't be resumed from another one.
+                eprintln!("Download {}: mirror {} failed: {}", download_id, url, e);
+                discard_partial_download(app, download_id, partial_file.as_deref());
+                update_download(app, download_id, true, |download| {
+                    download.last_error = Some(e.to_string());
+                })
+                .ok();
+                last_error = Some(e.to_string());
+            }
+            Err(e) => return fail_download(app, download_id, e.to_string()),
+        }
+    }
+
+    let error = last_error.unwrap_or_else(|| format!("No usable URL for download {}", download_id));
+    fail_download(app, download_id, error);
+}
+
+/// The URLs to try for `download`, in order: its mirrors, or just its URL.
+fn candidate_urls(download: &DownloadInfo) -> Vec<String> {
+    if download.mirrors.is_empty() {
+        vec![download.url.clone()]
+    } else {
+        download.mirrors.clone()
+    }
+}
+
+/// Fetches `url` with `handler`. Also returns the file a plugin was writing, which the caller
+/// cleans up if the transfer doesn't complete.
+async fn transfer_from(
+    app: &AppHandle,
+    download: &DownloadInfo,
+    url: &str,
+    handler: DownloadHandler,
+    download_dir: &Path,
+    cancel_token: CancellationToken,
+) -> (Result<PathBuf, DownloadError>, Option<PathBuf>) {
+    let download_id = download.id.as_str();
+    match handler {
+        DownloadHandler::Plugin(plugin) => {
+            let file_path = plugin_file_path(app, download, download_dir);
+            let input = serde_json::json!({
+                "action": "download",
+                "url": url,
+                "file_path": file_path,
+                "download_id": download_id,
+                "speed_limit": effective_speed_limit(app, download),
+            });
+            let result = execute_plugin(&plugin, "download", input, app, cancel_token)
+                .await
+                .map(PathBuf::from);
+            (result, Some(file_path))
+        }
+        DownloadHandler::Direct | DownloadHandler::GenericHttp => {
+            let result = download_with_retries(
+                app,
+                download_id,
+                url,
+                download_dir,
+                &download.filename,
+                cancel_token,
+            )
+            .await;
+            (result, None)
+        }
+    }
+}
+
+/// Tells the frontend why a download was refused or paused, with the numbers to show.
+fn emit_disk_space_warning(app: &AppHandle, download_id: &str, required: Option<u64>, available: u64) {
+    app.emit(
+        "download://disk-space",
+        serde_json::json!({
+            "downloadId": download_id,
+            "requiredBytes": required,
+            "availableBytes": available,
+            "minFreeBytes": diskspace::min_free_space(app),
+        }),
+    )
+    .ok();
+}
+
+/// Where a plugin should write: the file picked on an earlier attempt, so it can continue it, or
+/// a sanitized name that doesn't collide with an existing file. The choice is recorded as the
+/// download's partial file.
+fn plugin_file_path(app: &AppHandle, download: &DownloadInfo, download_dir: &Path) -> PathBuf {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let recorded = active_downloads.read().ok().and_then(|downloads| {
+        downloads
+            .downloads
+            .get(&download.id)
+            .and_then(|download| download.part_path.clone())
+    });
+    if let Some(part_path) = recorded {
+        return PathBuf::from(part_path);
+    }
+    let name = filename::sanitize(&download.filename);
+    claim_file_path(app, &download.id, download_dir, &name, false)
+        .unwrap_or_else(|_| download_dir.join(name))
+}
+
+/// Runs a direct transfer, retrying transient failures with exponential backoff. Each attempt
+/// resumes from the partial file the previous one left behind.
+async fn download_with_retries(
+    app: &AppHandle,
+    download_id: &str,
+    url: &str,
+    download_dir: &Path,
+    filename: &str,
+    cancel_token: CancellationToken,
+) -> Result<PathBuf, DownloadError> {
+    let max_retries = max_download_retries(app);
+    let mut attempt = 0;
+    loop {
+        let (message, retry_after) =
+            match download_direct(
+                app,
+                download_id,
+                url,
+                download_dir,
+                filename,
+                cancel_token.clone(),
+            )
+            .await
+            {
+                Err(DownloadError::Transient {
+                    message,
+                    retry_after,
+                }) if attempt < max_retries => (message, retry_after),
+                result => return result,
+            };
+        attempt += 1;
+
+        let delay = retry_delay(attempt, retry_after);
+        eprintln!(
+            "Download {} failed ({}), retrying in {:.1}s ({}/{})",
+            download_id,
+            message,
+            delay.as_secs_f32(),
+            attempt,
+            max_retries
+        );
+        let mut event = None;
+        update_download(app, download_id, true, |download| {
+            download.retry_count = attempt;
+            download.last_error = Some(message);
+            event = Some(progress_event(download));
+        })
+        .map_err(DownloadError::DownloadFailed)?;
+        if let Some(event) = event {
+            app.emit("download://progress", event).ok();
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel_token.cancelled() => return Err(DownloadError::Cancelled),
+        }
+    }
+}
+
+/// Exponential backoff with jitter, unless the server said how long to wait.
+fn retry_delay(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(retry_after) = retry_after {
+        return retry_after.min(MAX_RETRY_AFTER);
+    }
+    let backoff = BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_RETRY_DELAY);
+    // Spread retries of downloads that failed together over the upper half of the window.
+    backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+fn max_download_retries(app: &AppHandle) -> u32 {
+    let state = app.state::<Mutex<AppState>>();
+    let retries = state
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.max_download_retries);
+    retries.unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+/// Hashes a finished download and compares it with the checksum the article listed, if any,
+/// before marking it completed.
+async fn verify_download(app: &AppHandle, download: &DownloadInfo, path: &Path) {
+    let download_id = download.id.as_str();
+    // The partial file has been renamed by now; record the final path so a restart hashes it
+    // again instead of downloading it anew.
+    let final_path = path.to_string_lossy().to_string();
+    if let Err(e) = set_status(app, download_id, DownloadStatus::Verifying, true, |download| {
+        download.progress = 100.0;
+        download.path = Some(final_path);
+        download.part_path = None;
+        download.segments.clear();
+    }) {
+        eprintln!("{}", e);
+        return;
+    }
+
+    let hash_path = path.to_path_buf();
+    let hashed = tauri::async_runtime::spawn_blocking(move || checksum::hash_file(&hash_path))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()));
+    let digests = match hashed {
+        Ok(digests) => digests,
+        Err(e) => {
+            let status = DownloadStatus::Failed {
+                stage: FailureStage::Verification,
+                error: format!("Failed to hash download: {}", e),
+            };
+            set_status(app, download_id, status, true, |_| {}).ok();
+            return;
+        }
+    };
+
+    update_download(app, download_id, false, |download| {
+        download.sha1 = Some(digests.sha1.clone());
+        download.sha256 = Some(digests.sha256.clone());
+    })
+    .ok();
+
+    let Some(expected) = download.expected_checksum.as_deref() else {
+        complete_download(app, download_id, path);
+        return;
+    };
+    match checksum::verify(expected, &digests) {
+        Ok(true) => complete_download(app, download_id, path),
+        Ok(false) => corrupt_download(
+            app,
+            download_id,
+            path,
+            format!(
+                "Checksum mismatch: expected {}, got sha1 {} / sha256 {}",
+                expected, digests.sha1, digests.sha256
+            ),
+        ),
+        Err(e) => {
+            // A malformed checksum in the article shouldn't hold back a good download.
+            eprintln!("Skipping checksum verification for {}: {}", download_id, e);
+            complete_download(app, download_id, path);
+        }
+    }
+}
+
 pub async fn execute_plugin(
     plugin: &PluginManifest,
     action: &str,
@@ -24,9 +846,15 @@ pub async fn execute_plugin(
     app: &AppHandle,
     cancel_token: CancellationToken,
 ) -> Result<String, DownloadError> {
-    let result: DownloadResult = plugin_execute_plugin(plugin, action, input, app, cancel_token)
+    let result: DownloadResult = plugin_execute_plugin(plugin, action, input, app, cancel_token.clone())
         .await
-        .map_err(|e| DownloadError::DownloadFailed(e))?;
+        .map_err(|e| {
+            if cancel_token.is_cancelled() {
+                DownloadError::Cancelled
+            } else {
+                DownloadError::DownloadFailed(e)
+            }
+        })?;
 
     if !std::path::Path::new(&result.path).exists() {
         return Err(DownloadError::DownloadFailed(format!(
@@ -36,4 +864,4 @@ pub async fn execute_plugin(
     }
 
     Ok(result.path)
-}
\ No newline at end of file
+}
@@ -1,73 +1,54 @@
-use crate::state::save_active_downloads_to_file;
-use crate::types::{ActiveDownloads, DownloadInfo};
-use std::sync::RwLock;
+use crate::archiver::{ArchiveError, ExtractionProgress};
+use crate::state::{save_active_downloads_to_file, AppState};
+use crate::types::{ActiveDownloads, DownloadInfo, DownloadStatus, FailureStage, StopRequest};
+use crate::filename;
+use crate::httpdownload::part_path_for;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
 use tauri::{AppHandle, Emitter, Manager, State};
 
+/// Extracts `file_path` into `output_dir`, trying `passwords` and then the saved archive passwords
+/// on an encrypted archive.
 pub async fn unarchive_file(
     file_path: String,
     output_dir: String,
     download_id: String,
+    mut passwords: Vec<String>,
     app: AppHandle,
 ) -> Result<(), String> {
-    app.emit(
-        "extraction-progress",
-        &serde_json::json!({
-            "downloadId": download_id,
-            "status": "extracting",
-            "progress": 0.0
-        }),
-    )
-    .map_err(|e| format!("Failed to emit extraction progress: {}", e))?;
+    set_status(&app, &download_id, DownloadStatus::Extracting, true, |download| {
+        download.extraction_progress = Some(0.0);
+    })?;
+    emit_extraction(&app, &download_id, &DownloadStatus::Extracting, 0.0, None)
+        .map_err(|e| format!("Failed to emit extraction progress: {}", e))?;
 
-    {
-        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
-        let mut downloads = active_downloads
-            .write()
-            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
-        if let Some(download) = downloads.downloads.get_mut(&download_id) {
-            download.extraction_status = Some("extracting".to_string());
-            download.extraction_progress = Some(0.0);
-        }
-        save_active_downloads_to_file(&app, &downloads)?;
-    }
-
-    let result = crate::archiver::unarchive_file_with_progress(&file_path, &output_dir, |progress| {
-        app.emit(
-            "extraction-progress",
-            &serde_json::json!({
-                "downloadId": download_id,
-                "status": "extracting",
-                "progress": progress
-            }),
-        )
-        .ok();
-    });
+    if let Ok(app_state) = app.state::<Mutex<AppState>>().lock() {
+        passwords.extend(app_state.archive_passwords.iter().cloned());
+    }
+    let mut seen = std::collections::HashSet::new();
+    passwords.retain(|password| !password.is_empty() && seen.insert(password.clone()));
+
+    let min_free_space = crate::diskspace::min_free_space(&app);
+    let result = crate::archiver::unarchive_file_with_progress(
+        &file_path,
+        &output_dir,
+        min_free_space,
+        &passwords,
+        |progress| {
+            let status = DownloadStatus::Extracting;
+            emit_extraction(&app, &download_id, &status, progress.percent(), Some(progress)).ok();
+        },
+    );
 
     match result {
         Ok(_) => {
-            app.emit(
-                "extraction-progress",
-                &serde_json::json!({
-                    "downloadId": download_id,
-                    "status": "completed",
-                    "progress": 100.0
-                }),
-            )
-            .map_err(|e| format!("Failed to emit extraction complete: {}", e))?;
-
-            {
-                let active_downloads = app.state::<RwLock<ActiveDownloads>>();
-                let mut downloads = active_downloads
-                    .write()
-                    .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
-                if let Some(download) = downloads.downloads.get_mut(&download_id) {
-                    download.extraction_status = Some("completed".to_string());
-                    download.extraction_progress = Some(100.0);
-                    download.extracted = true;
-                    download.extracted_path = Some(output_dir.clone());
-                }
-                save_active_downloads_to_file(&app, &downloads)?;
-            }
+            set_status(&app, &download_id, DownloadStatus::Extracted, true, |download| {
+                download.extraction_progress = Some(100.0);
+                download.extracted = true;
+                download.extracted_path = Some(output_dir.clone());
+            })?;
+            emit_extraction(&app, &download_id, &DownloadStatus::Extracted, 100.0, None)
+                .map_err(|e| format!("Failed to emit extraction complete: {}", e))?;
 
             tauri_plugin_notification::NotificationExt::notification(&app)
                 .builder()
@@ -79,27 +60,17 @@ pub async fn unarchive_file(
             Ok(())
         }
         Err(e) => {
-            app.emit(
-                "extraction-progress",
-                &serde_json::json!({
-                    "downloadId": download_id,
-                    "status": "failed",
-                    "progress": 0.0,
-                    "error": e.to_string()
-                }),
-            )
-            .map_err(|e| format!("Failed to emit extraction error: {}", e))?;
-
-            {
-                let active_downloads = app.state::<RwLock<ActiveDownloads>>();
-                let mut downloads = active_downloads
-                    .write()
-                    .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
-                if let Some(download) = downloads.downloads.get_mut(&download_id) {
-                    download.extraction_status = Some("failed".to_string());
-                    download.extraction_progress = Some(0.0);
-                }
-                save_active_downloads_to_file(&app, &downloads)?;
+            let status = DownloadStatus::Failed {
+                stage: FailureStage::Extraction,
+                error: e.to_string(),
+            };
+            set_status(&app, &download_id, status.clone(), true, |download| {
+                download.extraction_progress = Some(0.0);
+            })?;
+            emit_extraction(&app, &download_id, &status, 0.0, None)
+                .map_err(|e| format!("Failed to emit extraction error: {}", e))?;
+            if matches!(e, ArchiveError::PasswordRequired | ArchiveError::WrongPassword) {
+                emit_password_prompt(&app, &download_id, &e, passwords.len());
             }
 
             Err(e.to_string())
@@ -107,6 +78,40 @@ pub async fn unarchive_file(
     }
 }
 
+/// Payload of an `extraction-progress` event: the status with its data and the percentage, plus
+/// byte and entry counts while the archive is being unpacked.
+fn emit_extraction(
+    app: &AppHandle,
+    download_id: &str,
+    status: &DownloadStatus,
+    progress: f32,
+    counts: Option<&ExtractionProgress>,
+) -> tauri::Result<()> {
+    let mut event = serde_json::to_value(status)?;
+    event["downloadId"] = download_id.into();
+    event["progress"] = progress.into();
+    if let Some(counts) = counts {
+        event["extractedBytes"] = counts.extracted_bytes.into();
+        event["totalBytes"] = counts.total_bytes.into();
+        event["extractedEntries"] = counts.extracted_entries.into();
+        event["totalEntries"] = counts.total_entries.into();
+    }
+    app.emit("extraction-progress", event)
+}
+
+/// Asks the frontend for a password to retry the extraction with.
+fn emit_password_prompt(app: &AppHandle, download_id: &str, error: &ArchiveError, tried: usize) {
+    app.emit(
+        "download://password-required",
+        serde_json::json!({
+            "downloadId": download_id,
+            "wrongPassword": matches!(error, ArchiveError::WrongPassword),
+            "triedPasswords": tried,
+        }),
+    )
+    .ok();
+}
+
 pub fn get_active_downloads(
     active_downloads: State<'_, RwLock<ActiveDownloads>>,
 ) -> Result<Vec<DownloadInfo>, String> {
@@ -116,6 +121,342 @@ pub fn get_active_downloads(
     Ok(downloads.downloads.values().cloned().collect())
 }
 
+/// Picks the path `download_id` saves `name` to in `dir` and records it as the download's partial
+/// file, `<path>.part` when `partial` is set. Both happen under one lock, so concurrent downloads
+/// of the same name can't pick the same path. A name is taken when the file or its `.part` file
+/// exists or another download claims it, except that a partial file left by a failed or cancelled
+/// download of the same URL is picked again so the new download continues it.
+pub fn claim_file_path(
+    app: &AppHandle,
+    download_id: &str,
+    dir: &Path,
+    name: &str,
+    partial: bool,
+) -> Result<PathBuf, String> {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let mut downloads = active_downloads
+        .write()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+
+    let file_path = filename::unique_path(dir, name, |path| {
+        let part_path = part_path_for(path);
+        let mut owners = downloads
+            .downloads
+            .values()
+            .filter(|download| download.id != download_id)
+            .filter(|download| {
+                download.part_path.as_deref().map(Path::new) == Some(path)
+                    || download.part_path.as_deref().map(Path::new) == Some(&part_path)
+            })
+            .peekable();
+        match owners.peek() {
+            Some(_) => {
+                owners.any(|download| !downloads.can_take_over(download_id, &download.id))
+                    || path.exists()
+            }
+            None => path.exists() || part_path.exists(),
+        }
+    });
+
+    let part_path = if partial {
+        part_path_for(&file_path)
+    } else {
+        file_path.clone()
+    };
+    if let Some(download) = downloads.downloads.get_mut(download_id) {
+        download.part_path = Some(part_path.to_string_lossy().to_string());
+    }
+    save_active_downloads_to_file(app, &downloads)?;
+    Ok(file_path)
+}
+
+/// Applies `update` to the tracked download, writing `active_downloads.json` only when `persist`
+/// is set so per-chunk progress updates stay in memory.
+pub fn update_download<F>(
+    app: &AppHandle,
+    download_id: &str,
+    persist: bool,
+    update: F,
+) -> Result<(), String>
+where
+    F: FnOnce(&mut DownloadInfo),
+{
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let mut downloads = active_downloads
+        .write()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+    if let Some(download) = downloads.downloads.get_mut(download_id) {
+        update(download);
+    }
+    if persist {
+        save_active_downloads_to_file(app, &downloads)?;
+    }
+    Ok(())
+}
+
+/// Moves the tracked download to `status`, applies `update` under the same lock and emits the
+/// result on `download://progress`. Transitions the state machine doesn't allow are rejected, so
+/// e.g. a transfer finishing late can't revive a cancelled download.
+pub fn set_status<F>(
+    app: &AppHandle,
+    download_id: &str,
+    status: DownloadStatus,
+    persist: bool,
+    update: F,
+) -> Result<(), String>
+where
+    F: FnOnce(&mut DownloadInfo),
+{
+    let event = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        let Some(download) = downloads.downloads.get_mut(download_id) else {
+            return Ok(());
+        };
+        let finished = matches!(status, DownloadStatus::Completed | DownloadStatus::Cancelled);
+        download.transition_to(status)?;
+        update(download);
+        let event = progress_event(download);
+        if finished {
+            downloads.browser_contexts.remove(download_id);
+        }
+        if persist {
+            save_active_downloads_to_file(app, &downloads)?;
+        }
+        event
+    };
+
+    app.emit("download://progress", event)
+        .map_err(|e| format!("Failed to emit download progress: {}", e))
+}
+
+/// Payload of a `download://progress` event: the status with its data, plus progress, byte
+/// counts, speed, ETA, path and retry information.
+pub fn progress_event(download: &DownloadInfo) -> serde_json::Value {
+    let mut event = serde_json::to_value(&download.status).unwrap_or_default();
+    event["downloadId"] = download.id.as_str().into();
+    event["progress"] = download.progress.into();
+    event["downloadedBytes"] = download.downloaded_bytes.into();
+    event["totalBytes"] = download.total_bytes.into();
+    event["speed"] = download.speed.into();
+    event["eta"] = download.eta.into();
+    if let Some(path) = &download.path {
+        event["path"] = path.as_str().into();
+    }
+    if download.retry_count > 0 {
+        event["retryCount"] = download.retry_count.into();
+        event["lastError"] = download.last_error.clone().into();
+    }
+    event
+}
+
+pub fn complete_download(app: &AppHandle, download_id: &str, path: &Path) {
+    let path = path.to_string_lossy().to_string();
+    let result = set_status(app, download_id, DownloadStatus::Completed, true, |download| {
+        download.progress = 100.0;
+        download.path = Some(path);
+        download.part_path = None;
+        download.segments.clear();
+        download.downloaded_at = Some(chrono::Utc::now().to_rfc3339());
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+/// Marks a finished download whose checksum didn't match. The file is kept, but the status keeps
+/// it from being treated as a completed archive.
+pub fn corrupt_download(app: &AppHandle, download_id: &str, path: &Path, error: String) {
+    let path = path.to_string_lossy().to_string();
+    let result = set_status(app, download_id, DownloadStatus::Corrupted { error }, true, |download| {
+        download.path = Some(path);
+        download.part_path = None;
+        download.segments.clear();
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+pub fn fail_download(app: &AppHandle, download_id: &str, error: String) {
+    if let Err(e) = set_status(app, download_id, DownloadStatus::failed(error), true, |_| {}) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Forgets the token of a transfer that has ended, along with any stop request it didn't get to.
+pub fn release_cancel_token(app: &AppHandle, download_id: &str) {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    if let Ok(mut downloads) = active_downloads.write() {
+        downloads.tokens.remove(download_id);
+        downloads.stop_requests.remove(download_id);
+    };
+}
+
+pub fn cancel_download(app: &AppHandle, download_id: &str, keep_partial: bool) -> Result<(), String> {
+    ensure_not_verifying(app, download_id, "cancelled")?;
+    let running = request_stop(app, download_id, StopRequest::Cancel { keep_partial })?;
+    if !running {
+        // Nothing is running, e.g. a queued download or a failed one that left its partial file
+        // behind. Check first, so a download that can't be cancelled keeps its files.
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let cancellable = active_downloads
+            .read()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?
+            .downloads
+            .get(download_id)
+            .map(|download| download.status.can_transition_to(&DownloadStatus::Cancelled));
+        if cancellable == Some(false) {
+            return Err(format!("Download {} can't be cancelled", download_id));
+        }
+        finish_cancelled_download(app, download_id, None, keep_partial);
+    }
+    Ok(())
+}
+
+/// Stops a running transfer but keeps its partial file and resume state, so `resume_download`
+/// can continue it later, including after a restart. A queued download is just taken out of the
+/// queue.
+pub fn pause_download(app: &AppHandle, download_id: &str) -> Result<(), String> {
+    ensure_not_verifying(app, download_id, "paused")?;
+    let paused_event = {
+        let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+        let mut downloads = active_downloads
+            .write()
+            .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+        match downloads.downloads.get_mut(download_id) {
+            Some(download) if download.status == DownloadStatus::Queued => {
+                download.transition_to(DownloadStatus::Paused)?;
+                let event = progress_event(download);
+                save_active_downloads_to_file(app, &downloads)?;
+                Some(event)
+            }
+            _ => None,
+        }
+    };
+    if let Some(event) = paused_event {
+        app.emit("download://progress", event)
+            .map_err(|e| format!("Failed to emit download progress: {}", e))?;
+        return Ok(());
+    }
+
+    if request_stop(app, download_id, StopRequest::Pause)? {
+        Ok(())
+    } else {
+        Err(format!("Download is not running: {}", download_id))
+    }
+}
+
+/// Hashing a finished file can't be interrupted, so neither pausing nor cancelling is accepted
+/// until verification is over.
+fn ensure_not_verifying(app: &AppHandle, download_id: &str, action: &str) -> Result<(), String> {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let downloads = active_downloads
+        .read()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+    let verifying = downloads
+        .downloads
+        .get(download_id)
+        .is_some_and(|download| download.status == DownloadStatus::Verifying);
+    if verifying {
+        return Err(format!(
+            "Download {} is being verified and can't be {} now",
+            download_id, action
+        ));
+    }
+    Ok(())
+}
+
+/// Records why a running transfer is being stopped and cancels its token. The transfer task
+/// notices the cancellation and calls `finish_stopped_download`. Returns whether a transfer was
+/// running.
+fn request_stop(app: &AppHandle, download_id: &str, request: StopRequest) -> Result<bool, String> {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let mut downloads = active_downloads
+        .write()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+    match downloads.tokens.get(download_id).cloned() {
+        Some(token) => {
+            downloads
+                .stop_requests
+                .insert(download_id.to_string(), request);
+            token.cancel();
+            Ok(true)
+        }
+        None if downloads.downloads.contains_key(download_id) => Ok(false),
+        None => Err(format!("Download not found: {}", download_id)),
+    }
+}
+
+/// Settles a transfer whose token was cancelled, according to the stop request that caused it.
+/// `partial_file` covers transfers whose partial file isn't recorded on the entry, such as plugin
+/// downloads.
+pub fn finish_stopped_download(app: &AppHandle, download_id: &str, partial_file: Option<&Path>) {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let request = active_downloads
+        .write()
+        .ok()
+        .and_then(|mut downloads| downloads.stop_requests.remove(download_id));
+
+    match request {
+        Some(StopRequest::Pause) => {
+            if let Err(e) = set_status(app, download_id, DownloadStatus::Paused, true, |_| {}) {
+                eprintln!("{}", e);
+            }
+        }
+        Some(StopRequest::Cancel { keep_partial }) => {
+            finish_cancelled_download(app, download_id, partial_file, keep_partial)
+        }
+        None => finish_cancelled_download(app, download_id, partial_file, false),
+    }
+}
+
+/// Marks a download as cancelled and removes its partial file unless asked to keep it.
+fn finish_cancelled_download(
+    app: &AppHandle,
+    download_id: &str,
+    partial_file: Option<&Path>,
+    keep_partial: bool,
+) {
+    if !keep_partial {
+        discard_partial_download(app, download_id, partial_file);
+    }
+    if let Err(e) = set_status(app, download_id, DownloadStatus::Cancelled, true, |_| {}) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Deletes the download's recorded partial file and `partial_file`, e.g. what a plugin was
+/// writing, and forgets the resume state that went with them.
+pub fn discard_partial_download(app: &AppHandle, download_id: &str, partial_file: Option<&Path>) {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let recorded_part = active_downloads.read().ok().and_then(|downloads| {
+        downloads
+            .downloads
+            .get(download_id)
+            .and_then(|download| download.part_path.clone())
+    });
+
+    let recorded_part = recorded_part.as_deref().map(Path::new);
+    for path in recorded_part.into_iter().chain(partial_file) {
+        if path.exists() {
+            if let Err(e) = std::fs::remove_file(path) {
+                eprintln!("Failed to remove partial file {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    update_download(app, download_id, true, |download| {
+        download.part_path = None;
+        download.resume_validator = None;
+        download.segments.clear();
+        download.downloaded_bytes = 0;
+    })
+    .ok();
+}
+
 pub fn register_manual_download(
     download_id: String,
     filename: String,
@@ -132,15 +473,14 @@ pub fn register_manual_download(
         filename,
         url: "manual".to_string(),
         progress: 100.0,
-        status: "completed".to_string(),
+        status: DownloadStatus::Completed,
         path: Some(path),
-        error: None,
         provider: Some("manual".to_string()),
         downloaded_at: Some(chrono::Utc::now().to_rfc3339()),
         extracted: false,
         extracted_path: None,
-        extraction_status: None,
         extraction_progress: None,
+        ..Default::default()
     };
     
     downloads.downloads.insert(download_id, download_info);
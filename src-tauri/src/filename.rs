@@ -0,0 +1,253 @@
+use percent_encoding::percent_decode;
+use std::path::{Path, PathBuf};
+
+/// Used when neither the server nor the URL provide a usable name.
+pub const FALLBACK_FILENAME: &str = "download";
+
+/// Longest name we create, in bytes; most filesystems allow 255.
+const MAX_FILENAME_LEN: usize = 200;
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Extracts the filename from a `Content-Disposition` header value. An RFC 5987 `filename*`
+/// wins over a plain `filename`, since that is where servers put non-ASCII names.
+pub fn from_content_disposition(value: &[u8]) -> Option<String> {
+    let value = String::from_utf8_lossy(value);
+    let params = parameters(&value);
+
+    let extended = params
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("filename*"))
+        .and_then(|(_, value)| decode_ext_value(value));
+    extended
+        .or_else(|| {
+            params
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("filename"))
+                .map(|(_, value)| value.clone())
+        })
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// The last path segment of `url`, percent-decoded.
+pub fn from_url(url: &url::Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let name = percent_decode(segment.as_bytes()).decode_utf8_lossy().to_string();
+    (!name.trim().is_empty()).then_some(name)
+}
+
+/// The file `url` points at, for hosts that put the name somewhere in the path, as in
+/// `/file/<key>/Game.zip/file`: the last segment with an extension. Ids such as MEGA's carry
+/// none, so `None` there.
+pub fn file_name_in_url(url: &url::Url) -> Option<String> {
+    url.path_segments()?
+        .rev()
+        .map(|segment| percent_decode(segment.as_bytes()).decode_utf8_lossy().to_string())
+        .find(|segment| {
+            let (stem, extension) = split_extension(segment);
+            let extension = extension.trim_start_matches('.');
+            !stem.trim().is_empty()
+                && (1..=8).contains(&extension.len())
+                && extension.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        })
+}
+
+/// Makes `name` safe to create inside the download directory: path separators, characters
+/// Windows rejects and control characters become `_`, reserved device names get a prefix and
+/// overly long names are shortened, keeping the extension.
+pub fn sanitize(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, which would turn `..` into an empty name.
+    let mut name = replaced.trim().trim_end_matches(['.', ' ']).to_string();
+
+    if name.is_empty() {
+        return FALLBACK_FILENAME.to_string();
+    }
+
+    let device = name.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(device.trim_end()))
+    {
+        name.insert(0, '_');
+    }
+
+    if name.len() > MAX_FILENAME_LEN {
+        let (stem, extension) = split_extension(&name);
+        let mut cut = MAX_FILENAME_LEN.saturating_sub(extension.len());
+        while !stem.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        name = format!("{}{}", &stem[..cut], extension);
+    }
+    name
+}
+
+/// `dir/name`, or `dir/name (1).ext`, `dir/name (2).ext`, ... if that one is `taken`.
+pub fn unique_path(dir: &Path, name: &str, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let candidate = dir.join(name);
+    if !taken(&candidate) {
+        return candidate;
+    }
+    let (stem, extension) = split_extension(name);
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !taken(path))
+        .unwrap_or(candidate)
+}
+
+/// Splits off the extension, keeping compound archive extensions such as `.tar.gz` together.
+fn split_extension(name: &str) -> (&str, &str) {
+    let lower = name.to_ascii_lowercase();
+    let dot = match lower.rfind(".tar.") {
+        Some(index) if index > 0 => Some(index),
+        _ => name.rfind('.').filter(|index| *index > 0),
+    };
+    match dot {
+        Some(index) => name.split_at(index),
+        None => (name, ""),
+    }
+}
+
+/// Splits the `;`-separated parameters of a header value into name/value pairs, unquoting quoted
+/// values. The disposition type itself is skipped.
+fn parameters(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    // Skip the disposition type (`attachment`, `inline`).
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+
+    loop {
+        let name: String = chars
+            .by_ref()
+            .take_while(|c| *c != '=')
+            .collect::<String>()
+            .trim()
+            .to_string();
+        if name.is_empty() {
+            break;
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut param_value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => param_value.extend(chars.next()),
+                    '"' => break,
+                    c => param_value.push(c),
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+            }
+        } else {
+            param_value = chars.by_ref().take_while(|c| *c != ';').collect();
+            param_value = param_value.trim().to_string();
+        }
+        params.push((name, param_value));
+    }
+    params
+}
+
+/// Decodes an RFC 5987 `charset'language'percent-encoded` value.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+    let bytes: Vec<u8> = percent_decode(encoded.as_bytes()).collect();
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_utf8_extended_filenames() {
+        let thai = b"attachment; filename*=UTF-8''%E0%B9%80%E0%B8%81%E0%B8%A1.zip";
+        assert_eq!(from_content_disposition(thai).as_deref(), Some("เกม.zip"));
+        let japanese = b"attachment; filename*=utf-8'ja'%E3%82%B2%E3%83%BC%E3%83%A0.7z";
+        assert_eq!(from_content_disposition(japanese).as_deref(), Some("ゲーム.7z"));
+    }
+
+    #[test]
+    fn prefers_extended_filename_over_plain() {
+        let value = b"attachment; filename=\"game.zip\"; filename*=UTF-8''%E3%82%B2%E3%83%BC%E3%83%A0.zip";
+        assert_eq!(from_content_disposition(value).as_deref(), Some("ゲーム.zip"));
+        let unknown_charset = b"attachment; filename=\"game.zip\"; filename*=koi8-r''%C9.zip";
+        assert_eq!(from_content_disposition(unknown_charset).as_deref(), Some("game.zip"));
+    }
+
+    #[test]
+    fn keeps_separators_inside_quotes() {
+        let value = br#"attachment; filename="part 1; \"final\".rar"; size=10"#;
+        assert_eq!(
+            from_content_disposition(value).as_deref(),
+            Some(r#"part 1; "final".rar"#)
+        );
+    }
+
+    #[test]
+    fn finds_file_names_in_host_urls() {
+        let name = |url: &str| file_name_in_url(&url::Url::parse(url).unwrap());
+        assert_eq!(
+            name("https://www.mediafire.com/file/abc123/Game_v1.part1.rar/file").as_deref(),
+            Some("Game_v1.part1.rar")
+        );
+        assert_eq!(
+            name("https://cdn.example.com/files/%E0%B9%80%E0%B8%81%E0%B8%A1.tar.gz").as_deref(),
+            Some("เกม.tar.gz")
+        );
+        assert_eq!(name("https://mega.nz/file/Xy7z1AbC#key"), None);
+        assert_eq!(name("https://drive.google.com/file/d/1AbCdEf/view"), None);
+    }
+
+    #[test]
+    fn sanitizes_paths_and_reserved_names() {
+        assert_eq!(sanitize("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(sanitize("CON.zip"), "_CON.zip");
+        assert_eq!(sanitize("lpt1"), "_lpt1");
+        assert_eq!(sanitize(" .. "), FALLBACK_FILENAME);
+    }
+
+    #[test]
+    fn shortens_long_names_keeping_compound_extensions() {
+        let name = sanitize(&format!("{}.tar.gz", "ก".repeat(100)));
+        assert!(name.len() <= MAX_FILENAME_LEN);
+        assert!(name.ends_with(".tar.gz"));
+    }
+
+    #[test]
+    fn numbers_taken_names() {
+        let dir = Path::new("downloads");
+        let taken = [dir.join("game.tar.gz"), dir.join("game (1).tar.gz")];
+        let path = unique_path(dir, "game.tar.gz", |path| taken.iter().any(|taken| taken == path));
+        assert_eq!(path, dir.join("game (2).tar.gz"));
+    }
+}
@@ -1,8 +1,9 @@
 use crate::state::{save_state_to_file, AppState, DownloadedGameInfo, LaunchConfig};
+use crate::types::ActiveDownloads;
 use std::fs;
 use std::path::Path;
 use std::process::Command as StdCommand;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 use tauri::{AppHandle, Manager, State};
 use tauri_plugin_dialog::DialogExt;
 use uuid::Uuid;
@@ -212,10 +213,31 @@ pub fn save_games(
         .lock()
         .map_err(|e| format!("Failed to lock state: {}", e))?;
     
+    // The frontend doesn't send the article link back, so keep the one we already know.
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let active_downloads = active_downloads
+        .read()
+        .map_err(|e| format!("Failed to lock active downloads: {}", e))?;
+    let known_slug = |id: &str| {
+        app_state
+            .games
+            .iter()
+            .flatten()
+            .find(|game| game.id == id)
+            .and_then(|game| game.article_slug.clone())
+            .or_else(|| {
+                active_downloads
+                    .downloads
+                    .get(id)
+                    .and_then(|download| download.article_slug.clone())
+            })
+    };
+
     // Convert DownloadInfo to DownloadedGameInfo
     let game_infos: Vec<DownloadedGameInfo> = games
         .into_iter()
         .map(|download| DownloadedGameInfo {
+            article_slug: download.article_slug.or_else(|| known_slug(&download.id)),
             id: download.id,
             filename: download.filename,
             path: download.path.unwrap_or_default(),
@@ -0,0 +1,971 @@
+// httpdownload.rs
+use crate::diskspace;
+use crate::downloadmanager::{global_speed_limit, DownloadError};
+use crate::downloads::{claim_file_path, progress_event, set_status, update_download};
+use crate::filename;
+use crate::proxy;
+use crate::state::AppState;
+use crate::throttle::{RateLimiter, SpeedMeter};
+use crate::types::{ActiveDownloads, DownloadInfo, DownloadSegment, DownloadStatus};
+use futures_util::future::try_join_all;
+use futures_util::StreamExt;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, COOKIE,
+    ETAG, IF_RANGE, LAST_MODIFIED, RANGE, REFERER, RETRY_AFTER, USER_AGENT,
+};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+
+pub const DEFAULT_CONNECTIONS: u32 = 1;
+pub const MAX_CONNECTIONS: u32 = 16;
+
+/// A file is only split when every connection gets at least this many bytes.
+const MIN_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How much a stream writes between two checks of the free space on its volume.
+const DISK_SPACE_CHECK_INTERVAL: u64 = 16 * 1024 * 1024;
+
+/// How often each connection of a running transfer flushes its writes and records them in
+/// `active_downloads.json`, so a crash loses at most this much of its progress.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How much of a page served instead of a file is read to find its title.
+const PAGE_SNIFF_LEN: usize = 16 * 1024;
+
+/// Longest page title quoted in an error message, in characters.
+const MAX_TITLE_LEN: usize = 100;
+
+/// Path of the partial file a direct download streams into until it is complete.
+pub fn part_path_for(file_path: &Path) -> PathBuf {
+    let mut part_path = file_path.as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+/// Streams `url` into a partial file in `download_dir` and renames it once the transfer is
+/// complete, returning the final path. The name comes from `Content-Disposition` when the server
+/// sends one, falling back to `filename`, and never overwrites an existing file. Large files are
+/// fetched over several connections when the server accepts byte ranges; otherwise a single
+/// stream is used, resuming from the partial file's length when possible. Unless the name says
+/// otherwise, a web page or JSON document served in place of the file is rejected. Cancelling
+/// `cancel_token` stops the transfer and leaves the partial file for the caller to deal with.
+pub async fn download_direct(
+    app: &AppHandle,
+    download_id: &str,
+    url: &str,
+    download_dir: &Path,
+    filename: &str,
+    cancel_token: CancellationToken,
+) -> Result<PathBuf, DownloadError> {
+    let mut transfer = Transfer {
+        app,
+        download_id,
+        url,
+        client: proxy::client(app).map_err(DownloadError::DownloadFailed)?,
+        headers: browser_headers(app, download_id),
+        cancel_token,
+        limiter: RateLimiter::new(),
+        meter: SpeedMeter::new(),
+        expects_file: true,
+    };
+
+    // The name is picked once; later attempts continue the partial file recorded for it.
+    let mut fresh_response = None;
+    let file_path = match recorded_file_path(app, download_id) {
+        Some(file_path) => file_path,
+        None => {
+            let response = check_status(transfer.send(transfer.client.get(url)).await?)?;
+            let name = response
+                .headers()
+                .get(CONTENT_DISPOSITION)
+                .and_then(|value| filename::from_content_disposition(value.as_bytes()))
+                .unwrap_or_else(|| filename.to_string());
+            fresh_response = Some(response);
+            let name = filename::sanitize(&name);
+            claim_file_path(app, download_id, download_dir, &name, true)
+                .map_err(DownloadError::DownloadFailed)?
+        }
+    };
+    let file_path = file_path.as_path();
+    transfer.expects_file = expects_file(file_path);
+    let part_path = part_path_for(file_path);
+    let part_path_str = part_path.to_string_lossy().to_string();
+
+    let part_len = tokio::fs::metadata(&part_path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let resume = take_resume_state(app, download_id, &part_path_str)?;
+
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    update_download(app, download_id, true, |download| {
+        if let Some(name) = name {
+            download.filename = name;
+        }
+        download.downloaded_bytes = 0;
+        download.total_bytes = resume.total;
+        download.part_path = Some(part_path_str.clone());
+        download.resume_validator = resume.validator.clone();
+        download.segments = resume.segments.clone();
+    })
+    .map_err(DownloadError::DownloadFailed)?;
+
+    // A segmented download preallocates the whole file, so the partial file's length says
+    // nothing about progress; continue each range from its recorded offset instead.
+    if part_len > 0 && !resume.segments.is_empty() {
+        if let Some(validator) = &resume.validator {
+            let downloaded: u64 = resume.segments.iter().map(|segment| segment.downloaded).sum();
+            ensure_space(app, &part_path, resume.total.map(|total| total.saturating_sub(downloaded)))?;
+            set_status(app, download_id, DownloadStatus::Downloading, false, |download| {
+                download.downloaded_bytes = downloaded;
+            })
+            .map_err(DownloadError::DownloadFailed)?;
+
+            match transfer
+                .fetch_segments(&part_path, &resume.segments, Some(validator))
+                .await
+            {
+                // The remote file changed since the ranges were recorded; start over below.
+                Err(DownloadError::RangesUnsupported) => {}
+                result => {
+                    result?;
+                    return finish_part_file(&part_path, file_path).await;
+                }
+            }
+        }
+    }
+
+    // Without a validator or a known size there is no way to tell whether the remote file
+    // changed since the partial file was written, so start over.
+    let mut offset = if resume.segments.is_empty()
+        && (resume.validator.is_some() || resume.total.is_some())
+    {
+        part_len
+    } else {
+        0
+    };
+
+    let mut response = match fresh_response {
+        Some(response) if offset == 0 => response,
+        _ => {
+            let mut request = transfer.client.get(url);
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+                if let Some(validator) = &resume.validator {
+                    request = request.header(IF_RANGE, validator);
+                }
+            }
+            transfer.send(request).await?
+        }
+    };
+
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        let remote_total = content_range(&response).and_then(|(_, total)| total);
+        if offset > 0 && remote_total == Some(offset) {
+            // The previous attempt received every byte but never got to rename the file.
+            return finish_part_file(&part_path, file_path).await;
+        }
+        offset = 0;
+        response = transfer.send(transfer.client.get(url)).await?;
+    }
+
+    let mut response = transfer.ensure_file(check_status(response)?).await?;
+
+    let resumed = offset > 0
+        && response.status() == StatusCode::PARTIAL_CONTENT
+        && content_range(&response).is_some_and(|(start, total)| {
+            start == offset && (resume.total.is_none() || total.is_none() || total == resume.total)
+        });
+    if !resumed {
+        // The server ignored the range or the file changed; the partial data is useless.
+        offset = 0;
+    }
+
+    let total_size = response.content_length().map(|len| len + offset);
+    let validator = resume_validator(&response);
+    ensure_space(app, &part_path, total_size.map(|total| total - offset))?;
+
+    if !resumed && accepts_ranges(&response) {
+        if let Some(segments) = plan_segments(total_size, connections(app)) {
+            drop(response);
+
+            let file = File::create(&part_path)
+                .await
+                .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+            file.set_len(total_size.unwrap_or(0))
+                .await
+                .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+            drop(file);
+
+            set_status(app, download_id, DownloadStatus::Downloading, true, |download| {
+                download.downloaded_bytes = 0;
+                download.total_bytes = total_size;
+                download.resume_validator = validator.clone();
+                download.segments = segments.clone();
+            })
+            .map_err(DownloadError::DownloadFailed)?;
+
+            match transfer
+                .fetch_segments(&part_path, &segments, validator.as_deref())
+                .await
+            {
+                // Advertised but not honoured; fall back to a single stream.
+                Err(DownloadError::RangesUnsupported) => {
+                    let fallback = check_status(transfer.send(transfer.client.get(url)).await?)?;
+                    response = transfer.ensure_file(fallback).await?;
+                }
+                result => {
+                    result?;
+                    return finish_part_file(&part_path, file_path).await;
+                }
+            }
+        }
+    }
+
+    let file = if resumed {
+        OpenOptions::new().append(true).open(&part_path).await
+    } else {
+        File::create(&part_path).await
+    };
+    let mut file = file.map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+
+    set_status(app, download_id, DownloadStatus::Downloading, true, |download| {
+        download.downloaded_bytes = offset;
+        download.total_bytes = total_size;
+        download.resume_validator = validator.clone();
+        download.segments = Vec::new();
+    })
+    .map_err(DownloadError::DownloadFailed)?;
+
+    transfer
+        .pump(response, &mut file, &part_path, !resumed, |_, _| {})
+        .await?;
+    drop(file);
+
+    finish_part_file(&part_path, file_path).await
+}
+
+/// Everything the streams of one direct download share.
+struct Transfer<'a> {
+    app: &'a AppHandle,
+    download_id: &'a str,
+    url: &'a str,
+    client: Client,
+    /// Sent with every request, so hosts see the webview's session.
+    headers: HeaderMap,
+    cancel_token: CancellationToken,
+    /// Per-download budget, shared by all connections of a segmented download.
+    limiter: RateLimiter,
+    meter: SpeedMeter,
+    /// Whether the body must not be a web page or JSON document.
+    expects_file: bool,
+}
+
+impl Transfer<'_> {
+    async fn send(&self, request: RequestBuilder) -> Result<Response, DownloadError> {
+        let request = request.headers(self.headers.clone());
+        tokio::select! {
+            response = request.send() => response.map_err(|e| {
+                if e.is_builder() {
+                    DownloadError::DownloadFailed(e.to_string())
+                } else {
+                    transient(e)
+                }
+            }),
+            _ = self.cancel_token.cancelled() => Err(DownloadError::Cancelled),
+        }
+    }
+
+    /// Rejects `response` if its `Content-Type` says it is a page rather than the expected file.
+    async fn ensure_file(&self, response: Response) -> Result<Response, DownloadError> {
+        if !self.expects_file {
+            return Ok(response);
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let Some(received) = page_kind_from_content_type(content_type) else {
+            return Ok(response);
+        };
+        let body = self.read_start(response).await;
+        Err(not_a_file(received, &body))
+    }
+
+    /// Up to `PAGE_SNIFF_LEN` bytes of the body, enough to find a page's title.
+    async fn read_start(&self, mut response: Response) -> Vec<u8> {
+        let mut body = Vec::new();
+        while body.len() < PAGE_SNIFF_LEN {
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk,
+                _ = self.cancel_token.cancelled() => break,
+            };
+            match chunk {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        body
+    }
+
+    /// Fetches the unfinished ranges in parallel, each into its own region of the partial file.
+    /// A server may send less than the range asked for, so the recorded progress decides whether
+    /// the file is whole; the holes left otherwise are retried.
+    async fn fetch_segments(
+        &self,
+        part_path: &Path,
+        segments: &[DownloadSegment],
+        validator: Option<&str>,
+    ) -> Result<(), DownloadError> {
+        let fetches = segments
+            .iter()
+            .enumerate()
+            .filter(|(_, segment)| !segment.is_complete())
+            .map(|(index, segment)| self.fetch_segment(part_path, index, segment, validator));
+        try_join_all(fetches).await?;
+
+        let active_downloads = self.app.state::<RwLock<ActiveDownloads>>();
+        let complete = active_downloads.read().ok().is_some_and(|downloads| {
+            downloads
+                .downloads
+                .get(self.download_id)
+                .is_some_and(|download| download.segments.iter().all(DownloadSegment::is_complete))
+        });
+        if !complete {
+            update_download(self.app, self.download_id, true, |_| {}).ok();
+            return Err(DownloadError::Transient {
+                message: "The server sent fewer bytes than requested".to_string(),
+                retry_after: None,
+            });
+        }
+        Ok(())
+    }
+
+    async fn fetch_segment(
+        &self,
+        part_path: &Path,
+        index: usize,
+        segment: &DownloadSegment,
+        validator: Option<&str>,
+    ) -> Result<(), DownloadError> {
+        let start = segment.start + segment.downloaded;
+        let mut request = self
+            .client
+            .get(self.url)
+            .header(RANGE, format!("bytes={}-{}", start, segment.end));
+        if let Some(validator) = validator {
+            request = request.header(IF_RANGE, validator);
+        }
+        let response = check_status(self.send(request).await?)?;
+
+        let honoured = response.status() == StatusCode::PARTIAL_CONTENT
+            && content_range(&response).is_some_and(|(range_start, _)| range_start == start);
+        if !honoured {
+            return Err(DownloadError::RangesUnsupported);
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(part_path)
+            .await
+            .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+        file.seek(SeekFrom::Start(start))
+            .await
+            .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+
+        self.pump(response, &mut file, part_path, start == 0, |download, len| {
+            if let Some(segment) = download.segments.get_mut(index) {
+                segment.downloaded += len;
+            }
+        })
+        .await
+    }
+
+    /// Copies the response body into `file`, keeping the transfer within its speed limits and
+    /// the free space reserve. `record` is handed the bytes that have reached the file since it was
+    /// last called, for resume state that must never count unwritten data. A body that starts the
+    /// file (`at_start`) is first checked not to be a page. On failure or cancellation the
+    /// progress made so far is persisted so the next attempt can pick up from there.
+    async fn pump<F>(
+        &self,
+        response: Response,
+        file: &mut File,
+        part_path: &Path,
+        at_start: bool,
+        mut record: F,
+    ) -> Result<(), DownloadError>
+    where
+        F: FnMut(&mut DownloadInfo, u64),
+    {
+        let mut unflushed = 0;
+        let copied = self
+            .copy_body(response, file, part_path, at_start, &mut record, &mut unflushed)
+            .await;
+        let flushed = file
+            .flush()
+            .await
+            .map_err(|e| DownloadError::DownloadFailed(e.to_string()));
+        if flushed.is_ok() {
+            update_download(self.app, self.download_id, copied.is_err(), |download| {
+                record(download, unflushed)
+            })
+            .ok();
+        } else if copied.is_err() {
+            update_download(self.app, self.download_id, true, |_| {}).ok();
+        }
+        copied.and(flushed)
+    }
+
+    async fn copy_body<F>(
+        &self,
+        response: Response,
+        file: &mut File,
+        part_path: &Path,
+        at_start: bool,
+        record: &mut F,
+        unflushed: &mut u64,
+    ) -> Result<(), DownloadError>
+    where
+        F: FnMut(&mut DownloadInfo, u64),
+    {
+        let mut stream = response.bytes_stream();
+        let mut last_checkpoint = Instant::now();
+        let min_free = diskspace::min_free_space(self.app);
+        let mut unchecked_bytes = 0;
+        let mut sniff = at_start && self.expects_file;
+
+        loop {
+            let item = tokio::select! {
+                biased;
+                _ = self.cancel_token.cancelled() => return Err(DownloadError::Cancelled),
+                item = stream.next() => item,
+            };
+            let Some(item) = item else {
+                return Ok(());
+            };
+            let chunk = item.map_err(transient)?;
+            if sniff && !chunk.is_empty() {
+                sniff = false;
+                if let Some(received) = page_kind_from_body(&chunk) {
+                    return Err(not_a_file(received, &chunk));
+                }
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+            let chunk_len = chunk.len() as u64;
+
+            unchecked_bytes += chunk_len;
+            if unchecked_bytes >= DISK_SPACE_CHECK_INTERVAL {
+                unchecked_bytes = 0;
+                if let Some(available) = diskspace::available_space(part_path) {
+                    if available < min_free {
+                        return Err(DownloadError::LowDiskSpace { available });
+                    }
+                }
+            }
+
+            // The data has to reach the file before resume state that counts it is recorded. Each
+            // connection flushes its own writes, so the others' counts stay as they were.
+            *unflushed += chunk_len;
+            let checkpoint = last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL;
+            let mut written = 0;
+            if checkpoint {
+                file.flush()
+                    .await
+                    .map_err(|e| DownloadError::DownloadFailed(e.to_string()))?;
+                last_checkpoint = Instant::now();
+                written = std::mem::take(unflushed);
+            }
+
+            // Progress is recorded for every chunk but only reported at the meter's pace.
+            let speed = self.meter.record(chunk_len);
+            let mut event = None;
+            let mut download_limit = None;
+            update_download(self.app, self.download_id, checkpoint, |download| {
+                download.downloaded_bytes += chunk_len;
+                if written > 0 {
+                    record(download, written);
+                }
+                download.progress = match download.total_bytes {
+                    Some(total) if total > 0 => (download.downloaded_bytes as f32 / total as f32) * 100.0,
+                    _ => 0.0, // Indeterminate
+                };
+                download_limit = download.speed_limit;
+                if let Some(speed) = speed {
+                    download.speed = Some(speed);
+                    download.eta = download
+                        .total_bytes
+                        .filter(|_| speed > 0)
+                        .map(|total| total.saturating_sub(download.downloaded_bytes) / speed);
+                    event = Some(progress_event(download));
+                }
+            })
+            .ok();
+            if let Some(event) = event {
+                self.app.emit("download://progress", event).ok();
+            }
+
+            self.throttle(chunk_len, download_limit).await;
+        }
+    }
+
+    /// Sleeps long enough to keep the transfer within the per-download and global limits. Both
+    /// are read on every chunk so changes in settings apply immediately.
+    async fn throttle(&self, chunk_len: u64, download_limit: Option<u64>) {
+        let download_wait = download_limit
+            .map(|limit| self.limiter.consume(chunk_len, limit))
+            .unwrap_or_default();
+        let global_wait = global_speed_limit(self.app)
+            .map(|limit| self.app.state::<RateLimiter>().consume(chunk_len, limit))
+            .unwrap_or_default();
+        let wait = download_wait.max(global_wait);
+        if !wait.is_zero() {
+            // A cancellation is picked up by the select in `copy_body`.
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.cancel_token.cancelled() => {}
+            }
+        }
+    }
+}
+
+/// Number of parallel connections configured for direct downloads.
+fn connections(app: &AppHandle) -> u32 {
+    let state = app.state::<Mutex<AppState>>();
+    let connections = state
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.download_connections);
+    connections
+        .unwrap_or(DEFAULT_CONNECTIONS)
+        .clamp(1, MAX_CONNECTIONS)
+}
+
+/// Splits `total_size` bytes into up to `connections` contiguous ranges, or `None` when the file
+/// is too small (or of unknown size) to be worth splitting.
+fn plan_segments(total_size: Option<u64>, connections: u32) -> Option<Vec<DownloadSegment>> {
+    let total = total_size?;
+    let count = (connections as u64).min(total / MIN_SEGMENT_SIZE);
+    if count < 2 {
+        return None;
+    }
+
+    let segment_size = total / count;
+    let segments = (0..count)
+        .map(|index| {
+            let start = index * segment_size;
+            let end = if index == count - 1 {
+                total - 1
+            } else {
+                start + segment_size - 1
+            };
+            DownloadSegment {
+                start,
+                end,
+                downloaded: 0,
+            }
+        })
+        .collect();
+    Some(segments)
+}
+
+/// Refuses to start when the `remaining` bytes plus the configured reserve don't fit on the volume
+/// holding `part_path`. Unknown sizes and volumes are let through.
+fn ensure_space(app: &AppHandle, part_path: &Path, remaining: Option<u64>) -> Result<(), DownloadError> {
+    let Some(remaining) = remaining else {
+        return Ok(());
+    };
+    let Some(available) = diskspace::available_space(part_path) else {
+        return Ok(());
+    };
+    let required = remaining.saturating_add(diskspace::min_free_space(app));
+    if available < required {
+        return Err(DownloadError::InsufficientSpace {
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Turns an error status into a `DownloadError`. Server errors and 429 are worth retrying.
+fn check_status(response: Response) -> Result<Response, DownloadError> {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+    let message = format!("HTTP {} for {}", status, response.url());
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        Err(DownloadError::Transient {
+            message,
+            retry_after: retry_after(&response),
+        })
+    } else {
+        Err(DownloadError::DownloadFailed(message))
+    }
+}
+
+/// Files whose name says they are a page or a document may legitimately be one.
+fn expects_file(file_path: &Path) -> bool {
+    let extension = file_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    !matches!(
+        extension.as_deref(),
+        Some("html" | "htm" | "xhtml" | "json" | "txt")
+    )
+}
+
+fn page_kind_from_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match mime.as_str() {
+        "text/html" | "application/xhtml+xml" => Some("a web page"),
+        "application/json" | "text/json" => Some("a JSON document"),
+        _ => None,
+    }
+}
+
+/// Looks at the first bytes of a body for servers that label their error pages as binary.
+fn page_kind_from_body(body: &[u8]) -> Option<&'static str> {
+    let start = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body);
+    let start = &start[start.iter().take_while(|byte| byte.is_ascii_whitespace()).count()..];
+    let head = String::from_utf8_lossy(&start[..start.len().min(64)]).to_ascii_lowercase();
+
+    if ["<!doctype html", "<html", "<head", "<body", "<title"]
+        .iter()
+        .any(|tag| head.starts_with(tag))
+    {
+        return Some("a web page");
+    }
+    // Binary formats don't start with `{`; only call it JSON if the text really is text.
+    let is_text = match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if head.starts_with('{') && is_text {
+        return Some("a JSON document");
+    }
+    None
+}
+
+fn not_a_file(received: &'static str, body: &[u8]) -> DownloadError {
+    let text = String::from_utf8_lossy(&body[..body.len().min(PAGE_SNIFF_LEN)]);
+    let title = if received == "a web page" {
+        page_title(&text)
+    } else {
+        Some(text.into_owned())
+    };
+    let title = title
+        .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|title| !title.is_empty())
+        .map(|title| match title.char_indices().nth(MAX_TITLE_LEN) {
+            Some((cut, _)) => format!("{}…", &title[..cut]),
+            None => title,
+        });
+    DownloadError::NotAFile { received, title }
+}
+
+/// The text of the page's `<title>` element, with the common entities decoded.
+fn page_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    Some(title)
+}
+
+/// Network errors (connection resets, timeouts, truncated bodies) are retried.
+fn transient(error: reqwest::Error) -> DownloadError {
+    DownloadError::Transient {
+        message: error.to_string(),
+        retry_after: None,
+    }
+}
+
+/// Parses `Retry-After`, given either in seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+fn accepts_ranges(response: &Response) -> bool {
+    response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"))
+}
+
+async fn finish_part_file(part_path: &Path, file_path: &Path) -> Result<PathBuf, DownloadError> {
+    tokio::fs::rename(part_path, file_path)
+        .await
+        .map_err(|e| DownloadError::DownloadFailed(format!("Failed to finalize download: {}", e)))?;
+    Ok(file_path.to_path_buf())
+}
+
+/// Headers replaying the webview session a download was intercepted from, if any. reqwest drops
+/// the cookies itself when a redirect leaves the host.
+fn browser_headers(app: &AppHandle, download_id: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let Ok(downloads) = active_downloads.read() else {
+        return headers;
+    };
+    let Some(context) = downloads.browser_contexts.get(download_id) else {
+        return headers;
+    };
+
+    for (name, value) in [
+        (COOKIE, &context.cookies),
+        (REFERER, &context.referer),
+        (USER_AGENT, &context.user_agent),
+    ] {
+        if let Some(value) = value.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+    if let Some(cookie) = headers.get_mut(COOKIE) {
+        cookie.set_sensitive(true);
+    }
+    headers
+}
+
+/// Final path of a download that already has a partial file from an earlier attempt.
+fn recorded_file_path(app: &AppHandle, download_id: &str) -> Option<PathBuf> {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let downloads = active_downloads.read().ok()?;
+    let part_path = downloads.downloads.get(download_id)?.part_path.clone()?;
+    part_path.strip_suffix(".part").map(PathBuf::from)
+}
+
+#[derive(Default)]
+struct ResumeState {
+    validator: Option<String>,
+    total: Option<u64>,
+    segments: Vec<DownloadSegment>,
+}
+
+/// Looks up what was recorded about `part_path` by an earlier attempt. Retrying a failed
+/// download usually happens under a fresh id, so an older failed or cancelled entry of the same
+/// URL owning the partial file is dropped in favour of the new one. A partial file still owned by
+/// a live or paused download, or by a different URL, is never shared.
+fn take_resume_state(
+    app: &AppHandle,
+    download_id: &str,
+    part_path: &str,
+) -> Result<ResumeState, DownloadError> {
+    let active_downloads = app.state::<RwLock<ActiveDownloads>>();
+    let Ok(mut downloads) = active_downloads.write() else {
+        return Ok(ResumeState::default());
+    };
+    recorded_resume_state(&mut downloads, download_id, part_path)
+}
+
+fn recorded_resume_state(
+    downloads: &mut ActiveDownloads,
+    download_id: &str,
+    part_path: &str,
+) -> Result<ResumeState, DownloadError> {
+    let owners: Vec<String> = downloads
+        .downloads
+        .values()
+        .filter(|download| download.part_path.as_deref() == Some(part_path))
+        .map(|download| download.id.clone())
+        .collect();
+
+    // Our own entry only has something to resume from once an attempt recorded a validator or
+    // the size, the same condition the transfer resumes on; until then it merely claims the file.
+    let mut previous = downloads.downloads.get(download_id).cloned().filter(|download| {
+        download.part_path.as_deref() == Some(part_path)
+            && (download.resume_validator.is_some() || download.total_bytes.is_some())
+    });
+    for owner in owners.iter().filter(|owner| *owner != download_id) {
+        if !downloads.can_take_over(download_id, owner) {
+            return Err(DownloadError::DownloadFailed(format!(
+                "{} belongs to another download",
+                part_path
+            )));
+        }
+        let abandoned = downloads.downloads.remove(owner);
+        if previous.is_none() {
+            previous = abandoned;
+        }
+    }
+    Ok(previous
+        .map(|download| ResumeState {
+            validator: download.resume_validator,
+            total: download.total_bytes,
+            segments: download.segments,
+        })
+        .unwrap_or_default())
+}
+
+/// Strong ETags are preferred; weak ones can't be used with `If-Range`, so fall back to
+/// `Last-Modified`.
+fn resume_validator(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get(LAST_MODIFIED).and_then(|value| value.to_str().ok()))
+        .map(|value| value.to_string())
+}
+
+/// Parses `Content-Range: bytes <start>-<end>/<total>` (or `bytes */<total>`) into the start
+/// offset and the total size, if known.
+fn content_range(response: &Response) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let start = match span.split_once('-') {
+        Some((start, _)) => start.trim().parse().ok()?,
+        None => 0,
+    };
+    Some((start, total.trim().parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracked(downloads: &mut ActiveDownloads, download: DownloadInfo) {
+        downloads.downloads.insert(download.id.clone(), download);
+    }
+
+    #[test]
+    fn resumes_own_entry_without_validator() {
+        let mut downloads = ActiveDownloads::default();
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "a".to_string(),
+                part_path: Some("game.zip.part".to_string()),
+                total_bytes: Some(1000),
+                ..Default::default()
+            },
+        );
+
+        let resume = recorded_resume_state(&mut downloads, "a", "game.zip.part").unwrap();
+        assert_eq!(resume.validator, None);
+        assert_eq!(resume.total, Some(1000));
+    }
+
+    #[test]
+    fn ignores_own_entry_that_only_claims_the_file() {
+        let mut downloads = ActiveDownloads::default();
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "a".to_string(),
+                part_path: Some("game.zip.part".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let resume = recorded_resume_state(&mut downloads, "a", "game.zip.part").unwrap();
+        assert_eq!(resume.total, None);
+    }
+
+    #[test]
+    fn refuses_partial_file_of_live_download() {
+        let mut downloads = ActiveDownloads::default();
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "a".to_string(),
+                status: DownloadStatus::Paused,
+                part_path: Some("game.zip.part".to_string()),
+                total_bytes: Some(1000),
+                ..Default::default()
+            },
+        );
+
+        assert!(recorded_resume_state(&mut downloads, "b", "game.zip.part").is_err());
+        assert!(downloads.downloads.contains_key("a"));
+    }
+
+    #[test]
+    fn takes_over_failed_download_of_same_url() {
+        let mut downloads = ActiveDownloads::default();
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "a".to_string(),
+                url: "https://mirror.example.com/game.zip".to_string(),
+                status: DownloadStatus::failed("connection reset"),
+                part_path: Some("game.zip.part".to_string()),
+                total_bytes: Some(1000),
+                ..Default::default()
+            },
+        );
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "b".to_string(),
+                url: "https://example.com/game.zip".to_string(),
+                mirrors: vec!["https://mirror.example.com/game.zip".to_string()],
+                part_path: Some("game.zip.part".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let resume = recorded_resume_state(&mut downloads, "b", "game.zip.part").unwrap();
+        assert_eq!(resume.total, Some(1000));
+        assert!(!downloads.downloads.contains_key("a"));
+    }
+
+    #[test]
+    fn keeps_failed_download_of_other_url() {
+        let mut downloads = ActiveDownloads::default();
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "a".to_string(),
+                url: "https://example.com/v1/game.zip".to_string(),
+                status: DownloadStatus::Cancelled,
+                part_path: Some("game.zip.part".to_string()),
+                ..Default::default()
+            },
+        );
+        tracked(
+            &mut downloads,
+            DownloadInfo {
+                id: "b".to_string(),
+                url: "https://example.com/v2/game.zip".to_string(),
+                ..Default::default()
+            },
+        );
+
+        assert!(recorded_resume_state(&mut downloads, "b", "game.zip.part").is_err());
+        assert!(downloads.downloads.contains_key("a"));
+    }
+}
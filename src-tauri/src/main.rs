@@ -5,23 +5,33 @@
 
 mod archiver;
 mod api;
+mod checksum;
 mod commands;
+mod diskspace;
 mod downloadmanager;
 mod downloads;
+mod filename;
 mod games;
+mod httpdownload;
 mod plugin;
+mod proxy;
 mod state;
+mod throttle;
 mod types;
 mod utils;
 
-use crate::state::{cleanup_active_downloads, load_active_downloads_from_file, load_state_from_file};
-use crate::types::{ActiveDownloads, PluginRegistry};
+use crate::state::{
+    cleanup_active_downloads, load_active_downloads_from_file, load_state_from_file,
+    save_active_downloads_to_file, AppState,
+};
+use crate::throttle::RateLimiter;
+use crate::types::{ActiveDownloads, BrowserContext, DownloadInfo, PluginRegistry};
 use crate::utils::get_plugins_path;
 use std::sync::{Mutex, RwLock};
 use tauri::Manager;
 use tauri::{
     webview::{DownloadEvent, WebviewWindowBuilder},
-    WebviewUrl,
+    Url, Webview, WebviewUrl,
 };
 use uuid;
 
@@ -36,18 +46,27 @@ fn main() {
             let _webview_window = WebviewWindowBuilder::from_config(handle, &config_window)?
                 .on_download(|_webview, event| {
                     if let DownloadEvent::Requested { url, .. } = event {
-                        let app_handle = _webview.app_handle();
-                        let download_id = uuid::Uuid::new_v4().to_string();
-                        let filename = url.path_segments().and_then(|s| s.last()).unwrap_or("unknown_file").to_string();
+                        let webview = _webview.clone();
+                        // Reading cookies from inside this handler deadlocks on Windows.
+                        tauri::async_runtime::spawn(async move {
+                            let app_handle = webview.app_handle();
+                            let download_id = uuid::Uuid::new_v4().to_string();
+                            let filename = filename::from_url(&url)
+                                .unwrap_or_else(|| filename::FALLBACK_FILENAME.to_string());
 
-                        let fut = commands::download_file(
-                            app_handle.clone(),
-                            download_id,
-                            url.to_string(),
-                            filename,
-                        );
-                        
-                        tauri::async_runtime::spawn(fut);
+                            let download = DownloadInfo {
+                                id: download_id,
+                                filename,
+                                url: url.to_string(),
+                                ..Default::default()
+                            };
+                            let context = browser_context(&webview, &url);
+                            if let Err(e) =
+                                downloadmanager::start_browser_download(app_handle, download, context)
+                            {
+                                eprintln!("Failed to start intercepted download {}: {}", url, e);
+                            }
+                        });
 
                         return false;
                     }
@@ -59,10 +78,14 @@ fn main() {
             let app_state = load_state_from_file(app.handle()).unwrap_or_default();
             app.manage(Mutex::new(app_state));
 
-            // Initialize active downloads
-            let active_downloads = load_active_downloads_from_file(app.handle()).unwrap_or_default();
+            // Initialize active downloads; transfers cut short by the last exit are queued again
+            let mut active_downloads = load_active_downloads_from_file(app.handle()).unwrap_or_default();
+            cleanup_active_downloads(&mut active_downloads);
             app.manage(RwLock::new(active_downloads));
 
+            // Shared bandwidth budget for the global speed limit
+            app.manage(RateLimiter::new());
+
             // Initialize plugin registry
             let mut plugin_registry = PluginRegistry::new();
             if let Ok(plugins_path) = get_plugins_path(app.handle()) {
@@ -72,6 +95,10 @@ fn main() {
             }
             app.manage(Mutex::new(plugin_registry));
 
+            // Pick up downloads that were still queued or being verified when the app last closed
+            downloadmanager::resume_verifications(app.handle());
+            downloadmanager::process_queue(app.handle());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -99,6 +126,32 @@ fn main() {
             
             // Downloads
             commands::download_file,
+            commands::download_from_mirrors,
+            commands::download_article,
+            commands::cancel_download,
+            commands::cancel_active_download,
+            commands::pause_download,
+            commands::resume_download,
+            commands::set_download_priority,
+            commands::reorder_download_queue,
+            commands::get_max_concurrent_downloads,
+            commands::set_max_concurrent_downloads,
+            commands::get_global_speed_limit,
+            commands::set_global_speed_limit,
+            commands::set_download_speed_limit,
+            commands::get_download_connections,
+            commands::set_download_connections,
+            commands::get_max_download_retries,
+            commands::set_max_download_retries,
+            commands::get_min_free_disk_space,
+            commands::set_min_free_disk_space,
+            commands::get_download_host_rules,
+            commands::set_download_host_rules,
+            commands::get_proxy_settings,
+            commands::set_proxy_settings,
+            commands::set_webview_user_agent,
+            commands::get_archive_passwords,
+            commands::set_archive_passwords,
             commands::unarchive_file,
             commands::get_active_downloads,
             commands::register_manual_download,
@@ -120,13 +173,50 @@ fn main() {
         ])
         .on_window_event(|_window, event| {
             if let tauri::WindowEvent::CloseRequested { .. } = event {
-                // Cleanup on app close
+                // Flush the latest progress so the next start can resume from it
                 let app_handle = _window.app_handle();
-                if let Ok(mut active_downloads) = app_handle.state::<RwLock<ActiveDownloads>>().write() {
-                    cleanup_active_downloads(&mut active_downloads);
+                if let Ok(active_downloads) = app_handle.state::<RwLock<ActiveDownloads>>().read() {
+                    if let Err(e) = save_active_downloads_to_file(app_handle, &active_downloads) {
+                        eprintln!("Failed to save active downloads: {}", e);
+                    }
                 }
             }
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+
+/// Cookies the webview holds for `url`, the page the download was started from and the webview's
+/// user agent.
+fn browser_context(webview: &Webview, url: &Url) -> BrowserContext {
+    let cookies = match webview.cookies_for_url(url.clone()) {
+        Ok(cookies) if !cookies.is_empty() => Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Failed to read webview cookies for {}: {}", url, e);
+            None
+        }
+    };
+    let referer = webview
+        .url()
+        .ok()
+        .filter(|page| matches!(page.scheme(), "http" | "https"))
+        .map(|page| page.to_string());
+    let user_agent = webview
+        .state::<Mutex<AppState>>()
+        .lock()
+        .ok()
+        .and_then(|app_state| app_state.webview_user_agent.clone());
+
+    BrowserContext {
+        cookies,
+        referer,
+        user_agent,
+    }
+}
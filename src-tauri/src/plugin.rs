@@ -1,4 +1,5 @@
 
+use crate::proxy;
 use crate::types::{PluginFunction, PluginManifest, PluginRegistry};
 use crate::utils::{find_external_binary, get_plugins_path, strip_ansi_codes};
 use serde::de::DeserializeOwned;
@@ -6,7 +7,9 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
 use tokio_util::sync::CancellationToken;
 use tauri::Manager;
 
@@ -144,7 +147,7 @@ pub async fn execute_plugin<T: DeserializeOwned>(
     action: &str,
     input: Value,
     app: &tauri::AppHandle,
-    _cancel_token: CancellationToken,
+    cancel_token: CancellationToken,
 ) -> Result<T, String> {
     match manifest.plugin_type.as_str() {
         "script" => {
@@ -172,18 +175,30 @@ pub async fn execute_plugin<T: DeserializeOwned>(
             cmd.args(shlex::split(&manifest.entry_point).unwrap_or_default().get(1..).unwrap_or(&[]))
                 .stdin(Stdio::piped())
                 .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
+                .stderr(Stdio::piped())
+                .kill_on_drop(true);
+            proxy::apply_to_command(app, &mut cmd)?;
 
             let mut child = cmd.spawn().map_err(|e| format!("Failed to start plugin: {}", e))?;
 
             if let Some(stdin) = child.stdin.as_mut() {
-                serde_json::to_writer(stdin, &input)
+                let payload = serde_json::to_vec(&input)
+                    .map_err(|e| format!("Failed to write to plugin: {}", e))?;
+                stdin
+                    .write_all(&payload)
+                    .await
                     .map_err(|e| format!("Failed to write to plugin: {}", e))?;
             }
 
-            let output = child
-                .wait_with_output()
-                .map_err(|e| format!("Plugin execution failed: {}", e))?;
+            // Dropping the wait future drops the child, and `kill_on_drop` terminates it.
+            let output = tokio::select! {
+                output = child.wait_with_output() => {
+                    output.map_err(|e| format!("Plugin execution failed: {}", e))?
+                }
+                _ = cancel_token.cancelled() => {
+                    return Err("Plugin execution cancelled".to_string());
+                }
+            };
 
             let stdout = strip_ansi_codes(&String::from_utf8_lossy(&output.stdout));
 
@@ -197,10 +212,18 @@ pub async fn execute_plugin<T: DeserializeOwned>(
             let program = parts.get(0).ok_or("Empty command")?;
             let args = parts.get(1..).unwrap_or(&[]);
 
-            let output = Command::new(program)
-                .args(args)
-                .output()
-                .map_err(|e| format!("Failed to execute command: {}", e))?;
+            let mut cmd = Command::new(program);
+            cmd.args(args).kill_on_drop(true);
+            proxy::apply_to_command(app, &mut cmd)?;
+
+            let output = tokio::select! {
+                output = cmd.output() => {
+                    output.map_err(|e| format!("Failed to execute command: {}", e))?
+                }
+                _ = cancel_token.cancelled() => {
+                    return Err("Plugin execution cancelled".to_string());
+                }
+            };
 
             let stdout = strip_ansi_codes(&String::from_utf8_lossy(&output.stdout));
 
@@ -0,0 +1,79 @@
+use crate::state::{AppState, ProxySettings};
+use reqwest::{Client, NoProxy, Proxy};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tokio::process::Command;
+
+const SUPPORTED_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// HTTP client for every outgoing request, routed through the configured proxy. Without a proxy
+/// setting the usual `HTTP_PROXY`/`HTTPS_PROXY` environment variables still apply.
+pub fn client(app: &AppHandle) -> Result<Client, String> {
+    let settings = settings(app);
+    let mut builder = Client::builder();
+    if let Some(proxy_url) = proxy_url(&settings)? {
+        let proxy = Proxy::all(proxy_url.as_str())
+            .map_err(|e| format!("Invalid proxy: {}", e))?
+            .no_proxy(NoProxy::from_string(&settings.no_proxy.join(",")));
+        builder = builder.proxy(proxy);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// Passes the proxy to a plugin subprocess through the standard proxy environment variables, in
+/// both spellings since tools disagree on which one they read.
+pub fn apply_to_command(app: &AppHandle, cmd: &mut Command) -> Result<(), String> {
+    let settings = settings(app);
+    let Some(proxy_url) = proxy_url(&settings)? else {
+        return Ok(());
+    };
+    for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+        cmd.env(name, proxy_url.as_str())
+            .env(name.to_lowercase(), proxy_url.as_str());
+    }
+    if !settings.no_proxy.is_empty() {
+        let no_proxy = settings.no_proxy.join(",");
+        cmd.env("NO_PROXY", &no_proxy).env("no_proxy", &no_proxy);
+    }
+    Ok(())
+}
+
+/// Checks that `settings` describe a proxy we can connect through.
+pub fn validate(settings: &ProxySettings) -> Result<(), String> {
+    proxy_url(settings).map(|_| ())
+}
+
+fn settings(app: &AppHandle) -> ProxySettings {
+    let state = app.state::<Mutex<AppState>>();
+    let settings = state
+        .lock()
+        .map(|app_state| app_state.proxy.clone())
+        .unwrap_or_default();
+    settings
+}
+
+/// The proxy URL with the credentials filled in, or `None` when no proxy is configured.
+fn proxy_url(settings: &ProxySettings) -> Result<Option<url::Url>, String> {
+    let Some(raw) = settings.url.as_deref().map(str::trim).filter(|url| !url.is_empty()) else {
+        return Ok(None);
+    };
+    let mut proxy_url = url::Url::parse(raw).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+    if !SUPPORTED_SCHEMES.contains(&proxy_url.scheme()) {
+        return Err(format!("Unsupported proxy scheme: {}", proxy_url.scheme()));
+    }
+    if proxy_url.host_str().is_none() {
+        return Err("Proxy URL has no host".to_string());
+    }
+
+    if let Some(username) = settings.username.as_deref().filter(|name| !name.is_empty()) {
+        proxy_url
+            .set_username(username)
+            .map_err(|_| "Invalid proxy username".to_string())?;
+        proxy_url
+            .set_password(settings.password.as_deref())
+            .map_err(|_| "Invalid proxy password".to_string())?;
+    }
+    Ok(Some(proxy_url))
+}
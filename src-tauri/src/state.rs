@@ -1,8 +1,9 @@
 use crate::ActiveDownloads;
+use crate::types::DownloadStatus;
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 
 
@@ -12,6 +13,85 @@ pub struct AppState {
     pub token: Option<String>,
     pub download_dir: Option<String>,
     pub games: Option<Vec<DownloadedGameInfo>>,
+    #[serde(default)]
+    pub max_concurrent_downloads: Option<usize>,
+    /// Global download bandwidth cap in bytes per second.
+    #[serde(default)]
+    pub max_download_speed: Option<u64>,
+    /// Parallel connections per direct download when the server accepts byte ranges.
+    #[serde(default)]
+    pub download_connections: Option<u32>,
+    /// Automatic retries after a transient failure such as a dropped connection or a 5xx.
+    #[serde(default)]
+    pub max_download_retries: Option<u32>,
+    /// Free space in bytes that downloads and extraction leave on the target volume.
+    #[serde(default)]
+    pub min_free_disk_space: Option<u64>,
+    #[serde(default)]
+    pub download_hosts: DownloadHostRules,
+    #[serde(default)]
+    pub proxy: ProxySettings,
+    /// Passwords tried on every encrypted archive after the ones given for it.
+    #[serde(default)]
+    pub archive_passwords: Vec<String>,
+    /// User agent of the webview, reported by the frontend on startup.
+    #[serde(skip)]
+    pub webview_user_agent: Option<String>,
+}
+
+/// Which hosts are downloaded directly, without a plugin. A rule matches the host itself and its
+/// subdomains; a leading `*.` is accepted too.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DownloadHostRules {
+    /// Hosts streamed directly.
+    pub allowed: Vec<String>,
+    /// Hosts never downloaded from, not even through a plugin.
+    pub blocked: Vec<String>,
+    /// Download from any other http(s) host as well, as long as the response is a file rather
+    /// than a web page.
+    pub generic_http: bool,
+}
+
+impl Default for DownloadHostRules {
+    fn default() -> Self {
+        Self {
+            allowed: vec!["chanomhub.online".to_string()],
+            blocked: Vec::new(),
+            generic_http: false,
+        }
+    }
+}
+
+impl DownloadHostRules {
+    pub fn is_allowed(&self, host: &str) -> bool {
+        self.allowed.iter().any(|rule| host_matches(rule, host))
+    }
+
+    pub fn is_blocked(&self, host: &str) -> bool {
+        self.blocked.iter().any(|rule| host_matches(rule, host))
+    }
+}
+
+fn host_matches(rule: &str, host: &str) -> bool {
+    let rule = rule.trim().trim_start_matches("*.").to_lowercase();
+    let host = host.to_lowercase();
+    !rule.is_empty()
+        && (host == rule
+            || host
+                .strip_suffix(&rule)
+                .is_some_and(|prefix| prefix.ends_with('.')))
+}
+
+/// Proxy used for API requests, downloads and plugins.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProxySettings {
+    /// `http://`, `https://` or `socks5://` URL of the proxy; no URL means a direct connection.
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Hosts reached without the proxy, in the same format as `NO_PROXY`.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -34,6 +114,9 @@ pub struct DownloadedGameInfo {
     pub downloaded_at: Option<String>,
     pub launch_config: Option<LaunchConfig>,
     pub icon_path: Option<String>,
+    /// Slug of the article the game was downloaded from.
+    #[serde(default)]
+    pub article_slug: Option<String>,
 }
 
 impl Default for AppState {
@@ -42,6 +125,15 @@ impl Default for AppState {
             token: None,
             download_dir: None,
             games: None,
+            max_concurrent_downloads: None,
+            max_download_speed: None,
+            download_connections: None,
+            max_download_retries: None,
+            min_free_disk_space: None,
+            download_hosts: DownloadHostRules::default(),
+            proxy: ProxySettings::default(),
+            archive_passwords: Vec::new(),
+            webview_user_agent: None,
         }
     }
 }
@@ -54,11 +146,12 @@ pub struct ArticleResponse {
 }
 
 pub async fn fetch_article_by_slug(
+    app: &AppHandle,
     slug: String,
     token: Option<String>,
 ) -> Result<ArticleResponse, String> {
     let api_url = format!("https://api.chanomhub.online/articles/{}", slug);
-    let client = reqwest::Client::new();
+    let client = crate::proxy::client(app)?;
     let mut request = client.get(&api_url);
 
     if let Some(token) = token {
@@ -136,10 +229,9 @@ pub fn save_state_to_file(app: &AppHandle, state: &AppState) -> Result<(), Strin
     fs::create_dir_all(&config_dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
 
     let config_path = config_dir.join("config.json");
-    let mut file = File::create(&config_path).map_err(|e| format!("Failed to create config file: {}", e))?;
     let json = serde_json::to_string_pretty(state)
         .map_err(|e| format!("Failed to serialize state: {}", e))?;
-    file.write_all(json.as_bytes())
+    write_atomically(&config_path, json.as_bytes())
         .map_err(|e| format!("Failed to write config file: {}", e))?;
     Ok(())
 }
@@ -152,15 +244,27 @@ pub fn save_active_downloads_to_file(
     fs::create_dir_all(&config_dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
 
     let downloads_path = config_dir.join("active_downloads.json");
-    let mut file = File::create(&downloads_path)
-        .map_err(|e| format!("Failed to create active downloads file: {}", e))?;
     let json = serde_json::to_string_pretty(active_downloads)
         .map_err(|e| format!("Failed to serialize active downloads: {}", e))?;
-    file.write_all(json.as_bytes())
+    write_atomically(&downloads_path, json.as_bytes())
         .map_err(|e| format!("Failed to write active downloads file: {}", e))?;
     Ok(())
 }
 
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so a crash
+/// halfway through leaves the previous version intact rather than a truncated file.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)
+}
+
 pub fn load_active_downloads_from_file(app: &AppHandle) -> Result<ActiveDownloads, String> {
     let config_dir = get_config_dir(app).ok_or("Could not get config directory")?;
     let downloads_path = config_dir.join("active_downloads.json");
@@ -168,7 +272,10 @@ pub fn load_active_downloads_from_file(app: &AppHandle) -> Result<ActiveDownload
     if downloads_path.exists() {
         let contents = fs::read_to_string(&downloads_path)
             .map_err(|e| format!("Failed to read active downloads file: {}", e))?;
-        let active_downloads: ActiveDownloads = serde_json::from_str(&contents)
+        let mut value: serde_json::Value = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse active downloads file: {}", e))?;
+        migrate_legacy_statuses(&mut value);
+        let active_downloads: ActiveDownloads = serde_json::from_value(value)
             .map_err(|e| format!("Failed to parse active downloads file: {}", e))?;
         Ok(active_downloads)
     } else {
@@ -176,11 +283,55 @@ pub fn load_active_downloads_from_file(app: &AppHandle) -> Result<ActiveDownload
     }
 }
 
+/// Files written before `DownloadStatus` kept the extraction state in a separate
+/// `extraction_status` string and allowed a `null` error on failed downloads.
+fn migrate_legacy_statuses(value: &mut serde_json::Value) {
+    let Some(downloads) = value
+        .get_mut("downloads")
+        .and_then(|downloads| downloads.as_object_mut())
+    else {
+        return;
+    };
+    for download in downloads.values_mut() {
+        let Some(download) = download.as_object_mut() else {
+            continue;
+        };
+        let extraction_status = download.remove("extraction_status");
+        let status = download.get("status").and_then(|status| status.as_str());
+        match (status, extraction_status.as_ref().and_then(|status| status.as_str())) {
+            (Some("completed"), Some("completed")) => {
+                download.insert("status".into(), "extracted".into());
+            }
+            (Some("completed"), Some("failed")) => {
+                download.insert("status".into(), "failed".into());
+                download.insert("stage".into(), "extraction".into());
+            }
+            _ => {}
+        }
+
+        let status = download.get("status").and_then(|status| status.as_str());
+        let has_error = download.get("error").is_some_and(|error| error.is_string());
+        if matches!(status, Some("failed" | "corrupted")) && !has_error {
+            download.insert("error".into(), "Unknown error".into());
+        }
+    }
+}
+
+/// Prepares downloads loaded at startup. Transfers the previous run didn't finish go back into the
+/// queue, so they continue from their partial files and last checkpoint. Downloads that were being
+/// verified are complete on disk and are left for `resume_verifications` to hash again.
 pub fn cleanup_active_downloads(active_downloads: &mut ActiveDownloads) {
     for download in active_downloads.downloads.values_mut() {
-        if download.status == "starting" || download.status == "downloading" {
-            download.status = "failed".to_string();
-            download.error = Some("Download interrupted due to application restart".to_string());
+        if download.status == DownloadStatus::Verifying {
+            download.speed = None;
+            download.eta = None;
+        } else if download.status.is_transferring() {
+            download.status = DownloadStatus::Queued;
+            download.speed = None;
+            download.eta = None;
+        } else if download.status == DownloadStatus::Extracting {
+            // The file itself is intact; extraction can simply be started again.
+            download.status = DownloadStatus::Completed;
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by every stream that draws from the same bandwidth budget. Callers report
+/// the bytes they just received and sleep for the returned delay before reading more.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    available: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter {
+            bucket: Mutex::new(Bucket {
+                available: 0.0,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` out of the bucket at `limit` bytes per second and returns how long to wait
+    /// until the bucket is no longer in debt. At most one second worth of unused budget is kept,
+    /// so an idle limiter doesn't allow a huge burst afterwards.
+    pub fn consume(&self, bytes: u64, limit: u64) -> Duration {
+        if limit == 0 {
+            return Duration::ZERO;
+        }
+        let Ok(mut bucket) = self.bucket.lock() else {
+            return Duration::ZERO;
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.last_refill = now;
+        bucket.available = (bucket.available + elapsed * limit as f64).min(limit as f64);
+        bucket.available -= bytes as f64;
+
+        if bucket.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.available / limit as f64)
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Minimum time between two progress events of the same download.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Weight of the newest sample in the moving average; lower values smooth out bursts more.
+const SPEED_SMOOTHING: f64 = 0.3;
+
+/// Exponential moving average of a transfer's speed, shared by all of its connections. It also
+/// paces progress events: a new estimate is produced at most once per `PROGRESS_INTERVAL`.
+pub struct SpeedMeter {
+    window: Mutex<Window>,
+}
+
+struct Window {
+    bytes: u64,
+    started: Instant,
+    speed: Option<f64>,
+}
+
+impl SpeedMeter {
+    pub fn new() -> Self {
+        SpeedMeter {
+            window: Mutex::new(Window {
+                bytes: 0,
+                started: Instant::now(),
+                speed: None,
+            }),
+        }
+    }
+
+    /// Counts `bytes` towards the current window. Once the window is `PROGRESS_INTERVAL` old it
+    /// is folded into the average, which is returned in bytes per second.
+    pub fn record(&self, bytes: u64) -> Option<u64> {
+        let Ok(mut window) = self.window.lock() else {
+            return None;
+        };
+        window.bytes += bytes;
+        let elapsed = window.started.elapsed();
+        if elapsed < PROGRESS_INTERVAL {
+            return None;
+        }
+
+        let current = window.bytes as f64 / elapsed.as_secs_f64();
+        let speed = match window.speed {
+            Some(previous) => previous + SPEED_SMOOTHING * (current - previous),
+            None => current,
+        };
+        window.speed = Some(speed);
+        window.bytes = 0;
+        window.started = Instant::now();
+        Some(speed as u64)
+    }
+}
+
+impl Default for SpeedMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -9,23 +9,281 @@ pub struct ActiveDownloads {
     pub downloads: HashMap<String, DownloadInfo>,
     #[serde(skip)]
     pub tokens: HashMap<String, CancellationToken>,
+    /// Why a transfer's token was cancelled, keyed by download id.
+    #[serde(skip)]
+    pub stop_requests: HashMap<String, StopRequest>,
+    /// Session of the webview a download was intercepted from, keyed by download id. Kept in
+    /// memory only, so cookies never end up in `active_downloads.json`.
+    #[serde(skip)]
+    pub browser_contexts: HashMap<String, BrowserContext>,
+}
+
+impl ActiveDownloads {
+    /// Whether `download_id` is a failed or cancelled download with nothing running for it, so
+    /// another download may take over its partial file.
+    pub fn is_abandoned(&self, download_id: &str) -> bool {
+        !self.tokens.contains_key(download_id)
+            && self.downloads.get(download_id).is_some_and(|download| {
+                matches!(
+                    download.status,
+                    DownloadStatus::Failed { .. } | DownloadStatus::Cancelled
+                )
+            })
+    }
+
+    /// Whether `download_id` may continue the partial file of `owner_id`: the owner was abandoned
+    /// and fetched the same file, judged by a URL the two have in common. Two downloads that only
+    /// share a file name must not be spliced together.
+    pub fn can_take_over(&self, download_id: &str, owner_id: &str) -> bool {
+        let (Some(download), Some(owner)) = (
+            self.downloads.get(download_id),
+            self.downloads.get(owner_id),
+        ) else {
+            return false;
+        };
+        let sources = |download: &DownloadInfo| {
+            std::iter::once(download.url.clone()).chain(download.mirrors.clone())
+        };
+        self.is_abandoned(owner_id)
+            && sources(download).any(|url| sources(owner).any(|other| other == url))
+    }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// What the webview would have sent along with a download request.
+#[derive(Debug, Clone, Default)]
+pub struct BrowserContext {
+    /// Value of the `Cookie` header.
+    pub cookies: Option<String>,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopRequest {
+    Cancel { keep_partial: bool },
+    Pause,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct DownloadInfo {
     pub id: String,
     pub filename: String,
     pub url: String,
+    /// Candidate URLs for the same file, tried in order until one works; `url` is the one in use.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
     pub progress: f32,
-    pub status: String,
+    /// Serialized inline, so the JSON keeps a plain `status` string next to the variant's fields.
+    #[serde(flatten)]
+    pub status: DownloadStatus,
     pub path: Option<String>,
-    pub error: Option<String>,
     pub provider: Option<String>,
     pub downloaded_at: Option<String>,
     pub extracted: bool,
     pub extracted_path: Option<String>,
-    pub extraction_status: Option<String>,
     pub extraction_progress: Option<f32>,
+    /// Bytes already written to `part_path`, i.e. the offset a resumed transfer continues from.
+    #[serde(default)]
+    pub downloaded_bytes: u64,
+    #[serde(default)]
+    pub total_bytes: Option<u64>,
+    #[serde(default)]
+    pub part_path: Option<String>,
+    /// ETag or Last-Modified of the remote file, sent as `If-Range` when resuming.
+    #[serde(default)]
+    pub resume_validator: Option<String>,
+    /// Queued downloads with a higher priority start first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Order among queued downloads of the same priority.
+    #[serde(default)]
+    pub queue_position: u64,
+    /// The chanomhub article this download belongs to, for the games library.
+    #[serde(default)]
+    pub article_id: Option<i64>,
+    #[serde(default)]
+    pub article_slug: Option<String>,
+    /// Moving-average transfer speed in bytes per second while downloading.
+    #[serde(default)]
+    pub speed: Option<u64>,
+    /// Estimated seconds left, when both the speed and the total size are known.
+    #[serde(default)]
+    pub eta: Option<u64>,
+    /// Bandwidth cap for this download in bytes per second, on top of the global one.
+    #[serde(default)]
+    pub speed_limit: Option<u64>,
+    /// Byte ranges of a multi-connection download; empty for single-stream transfers.
+    #[serde(default)]
+    pub segments: Vec<DownloadSegment>,
+    /// Checksum listed by the article, e.g. `sha256:<hex>`; verified once the download finishes.
+    #[serde(default)]
+    pub expected_checksum: Option<String>,
+    #[serde(default)]
+    pub sha1: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Automatic retries made since the download was last queued.
+    #[serde(default)]
+    pub retry_count: u32,
+    /// The transient error that triggered the most recent retry.
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl DownloadInfo {
+    /// Moves the download to `status` if `can_transition_to` allows it. Speed and ETA only mean
+    /// something while downloading, so they are cleared otherwise.
+    pub fn transition_to(&mut self, status: DownloadStatus) -> Result<(), String> {
+        if !self.status.can_transition_to(&status) {
+            return Err(format!(
+                "Download {} cannot go from {} to {}",
+                self.id, self.status, status
+            ));
+        }
+        if status != DownloadStatus::Downloading {
+            self.speed = None;
+            self.eta = None;
+        }
+        self.status = status;
+        Ok(())
+    }
+}
+
+/// Lifecycle of a download, from the queue through verification to extraction.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum DownloadStatus {
+    #[default]
+    Queued,
+    Starting,
+    Downloading,
+    Paused,
+    Verifying,
+    Completed,
+    /// The file was downloaded but its checksum didn't match.
+    Corrupted { error: String },
+    Failed {
+        #[serde(default)]
+        stage: FailureStage,
+        error: String,
+    },
+    Cancelled,
+    Extracting,
+    Extracted,
+}
+
+/// Which step a failed download broke in.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureStage {
+    #[default]
+    Download,
+    Verification,
+    Extraction,
+}
+
+impl DownloadStatus {
+    pub fn failed(error: impl Into<String>) -> Self {
+        DownloadStatus::Failed {
+            stage: FailureStage::Download,
+            error: error.into(),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadStatus::Queued => "queued",
+            DownloadStatus::Starting => "starting",
+            DownloadStatus::Downloading => "downloading",
+            DownloadStatus::Paused => "paused",
+            DownloadStatus::Verifying => "verifying",
+            DownloadStatus::Completed => "completed",
+            DownloadStatus::Corrupted { .. } => "corrupted",
+            DownloadStatus::Failed { .. } => "failed",
+            DownloadStatus::Cancelled => "cancelled",
+            DownloadStatus::Extracting => "extracting",
+            DownloadStatus::Extracted => "extracted",
+        }
+    }
+
+    /// A transfer owns the download in these states; after a restart nothing does.
+    pub fn is_transferring(&self) -> bool {
+        matches!(
+            self,
+            DownloadStatus::Starting | DownloadStatus::Downloading | DownloadStatus::Verifying
+        )
+    }
+
+    /// Whether the file on disk is finished, so it can be (re-)extracted.
+    pub fn is_downloaded(&self) -> bool {
+        matches!(
+            self,
+            DownloadStatus::Completed
+                | DownloadStatus::Extracting
+                | DownloadStatus::Extracted
+                | DownloadStatus::Failed {
+                    stage: FailureStage::Extraction,
+                    ..
+                }
+        )
+    }
+
+    pub fn can_transition_to(&self, next: &DownloadStatus) -> bool {
+        use DownloadStatus::*;
+        if self.as_str() == next.as_str() {
+            return true;
+        }
+        match (self, next) {
+            // Any download that isn't finished can be failed or cancelled.
+            (Queued | Starting | Downloading | Paused | Verifying, Failed { .. } | Cancelled) => {
+                true
+            }
+            (Queued, Starting | Paused) => true,
+            (Starting, Downloading | Verifying | Paused) => true,
+            (Downloading, Verifying | Paused) => true,
+            (Verifying, Completed | Corrupted { .. }) => true,
+            (Paused | Failed { .. } | Cancelled | Corrupted { .. }, Queued) => true,
+            (Corrupted { .. }, Cancelled) => true,
+            // Dropping what a failed transfer left behind; a failed extraction has a whole file.
+            (
+                Failed {
+                    stage: FailureStage::Download | FailureStage::Verification,
+                    ..
+                },
+                Cancelled,
+            ) => true,
+            (current, Extracting) => current.is_downloaded(),
+            (Extracting, Extracted | Completed) => true,
+            (
+                Extracting,
+                Failed {
+                    stage: FailureStage::Extraction,
+                    ..
+                },
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One byte range of a segmented download. `end` is inclusive.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct DownloadSegment {
+    pub start: u64,
+    pub end: u64,
+    pub downloaded: u64,
+}
+
+impl DownloadSegment {
+    pub fn is_complete(&self) -> bool {
+        self.downloaded > self.end - self.start
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
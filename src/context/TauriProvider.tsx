@@ -1,5 +1,6 @@
 import React, { useState, useEffect, useContext, ReactNode } from "react";
 import * as tauriPath from "@tauri-apps/api/path";
+import { invoke } from "@tauri-apps/api/core";
 import tauriConfJson from "../../src-tauri/tauri.conf.json" with { type: "json" };
 import * as fs from '@tauri-apps/plugin-fs';
 import * as os from "@tauri-apps/plugin-os";
@@ -39,6 +40,9 @@ export function TauriProvider({ children }: { children: ReactNode }) {
         if (RUNNING_IN_TAURI) {
             const callTauriAPIs = async () => {
                 try {
+                    // Sent along with downloads intercepted from the webview.
+                    await invoke("set_webview_user_agent", { userAgent: navigator.userAgent });
+
                     const downloadPath = await tauriPath.downloadDir();
                     setDownloadDir(downloadPath);
 
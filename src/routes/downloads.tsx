@@ -334,7 +334,7 @@ export default function DownloadManager() {
                                         </div>
                                     )}
 
-                                    {download.status === "completed" && (
+                                    {(download.status === "completed" || download.status === "extracted") && (
                                         <div className="space-y-2">
                                             <div className="flex justify-between items-center">
                                                 <span className="text-green-400 font-medium">Download complete</span>
@@ -317,7 +317,11 @@ const Games: React.FC = () => {
             try {
                 setLoading(true);
                 const activeDownloads: DownloadedFile[] = await invoke('get_active_downloads');
-                const completedFiles = activeDownloads.filter((file) => file.status === 'completed');
+                const completedFiles = activeDownloads.filter(
+                    (file) =>
+                        ['completed', 'extracting', 'extracted'].includes(file.status) ||
+                        (file.status === 'failed' && file.stage === 'extraction')
+                );
                 const savedGames: SavedGameInfo[] = await invoke('get_saved_games');
 
                 const processedActiveFiles = await Promise.all(
@@ -380,17 +384,18 @@ const Games: React.FC = () => {
     useEffect(() => {
         const unsubscribe = listen('extraction-progress', (event: any) => {
             const { downloadId, status, progress, error } = event.payload;
+            const extractionStatus = status === 'extracted' ? 'completed' : status;
             setFiles((prev) =>
                 prev.map((file) =>
                     file.id === downloadId
                         ? {
                             ...file,
-                            extractionStatus: status,
+                            extractionStatus,
                             extractionProgress: progress,
                             error: error || file.error,
-                            extracted: status === 'completed' ? true : file.extracted,
+                            extracted: extractionStatus === 'completed' ? true : file.extracted,
                             extractedPath:
-                                status === 'completed' ? `${file.path}_extracted` : file.extractedPath,
+                                extractionStatus === 'completed' ? `${file.path}_extracted` : file.extractedPath,
                         }
                         : file
                 )
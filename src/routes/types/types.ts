@@ -3,7 +3,20 @@ export interface DownloadItem {
     filename: string;
     url: string;
     progress: number;
-    status: "pending" | "downloading" | "completed" | "failed" | "cancelled";
+    status:
+        | "pending"
+        | "queued"
+        | "starting"
+        | "downloading"
+        | "paused"
+        | "verifying"
+        | "completed"
+        | "corrupted"
+        | "failed"
+        | "cancelled"
+        | "extracting"
+        | "extracted";
+    stage?: "download" | "verification" | "extraction";
     path?: string;
     error?: string;
     provider?: string;
@@ -29,6 +42,7 @@ export interface DownloadedFile {
     url?: string;
     provider?: string;
     error?: string | null;
+    stage?: 'download' | 'verification' | 'extraction';
     progress?: number;
     extractionStatus?: 'idle' | 'extracting' | 'completed' | 'failed';
     extractionProgress?: number;